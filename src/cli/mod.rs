@@ -1,14 +1,29 @@
 mod utils;
 
-use crate::config::Config;
-use crate::input;
+use crate::config::cheatsheet::Cheatsheet;
+use crate::config::fmt;
+use crate::config::import::Import;
+use crate::config::model::ConfigFile;
+use crate::config::{self, Config};
+use crate::executor::Executor;
+use crate::input::inspect;
+use crate::input::monitor;
+use crate::input::pipeline::{self, Pipeline};
+use crate::input::record::{self, Record};
+use crate::input::simulate;
+use crate::input::sink::{FakeSink, Sinks};
+use crate::input::source::ReplaySource;
+use crate::server::ipc::{self, Request};
 use crate::server::Server;
 
-use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
-use clap_verbosity_flag::{InfoLevel, Verbosity};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_verbosity_flag::Verbosity;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
 
 // Error Handling
-use crate::error::MudrasError;
+use crate::error::{LibError, MudrasError};
 use miette::Result;
 use tracing::info;
 
@@ -23,8 +38,129 @@ pub struct Cli {
 
 #[derive(Debug, Subcommand, Clone, Eq, PartialEq)]
 pub enum Commands {
-    #[command()]
-    Run,
+    /// Grab devices and listen to binds.
+    Run {
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Send a request to the running daemon.
+    #[command(subcommand)]
+    Msg(Request),
+    /// Record input events to a file, until interrupted.
+    Record {
+        file: PathBuf,
+        /// Device path to record (every keyboard and pointer by default).
+        #[arg(long)]
+        device: Vec<String>,
+    },
+    /// Replay a recording against the configuration and print forwarded events.
+    /// Bound actions are not executed unless --exec is given.
+    Replay {
+        file: PathBuf,
+        /// Execute bound actions.
+        #[arg(long)]
+        exec: bool,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Dry run a key sequence (ex: "Super down, n down, n up, Super up").
+    /// Nothing is spawned nor sent to devices.
+    Simulate {
+        sequence: String,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print pressed keys with their configuration names, until interrupted.
+    /// Devices are not grabbed.
+    Keys {
+        /// Device path to listen to (every keyboard and pointer by default).
+        #[arg(long)]
+        device: Vec<String>,
+        /// Show the binds the keys would trigger (only when --config is given).
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print the configured binds, grouped by submap.
+    Cheatsheet {
+        #[arg(long, value_enum, default_value_t)]
+        format: CheatsheetFormat,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// List input devices and how they are handled.
+    Devices {
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Rewrite configuration files in the canonical format.
+    /// TOML and JSON files are printed translated to KDL instead.
+    Fmt {
+        /// Files to format (the configuration file by default).
+        files: Vec<PathBuf>,
+        /// Only list the files that are not formatted, and fail if any.
+        #[arg(long)]
+        check: bool,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print the JSON Schema of TOML and JSON configuration files.
+    Schema,
+    /// Translate a keybinding file of another daemon to a configuration.
+    /// What can't be translated is reported on stderr.
+    Import {
+        #[arg(long, value_enum)]
+        from: ImportFormat,
+        file: PathBuf,
+    },
+}
+
+/// Where to find the configuration.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Args)]
+pub struct ConfigArgs {
+    /// Configuration file.
+    /// Default to $MUDRAS_CONFIG, then to the first existing file of
+    /// $XDG_CONFIG_HOME/mudras/config.kdl, ~/.config/mudras/config.kdl and /etc/mudras/config.kdl.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Resolve "~" against the home directory of this user.
+    /// Default to $SUDO_USER.
+    #[arg(long)]
+    pub user: Option<String>,
+}
+
+impl ConfigArgs {
+    fn set_user(&self) -> Result<(), MudrasError> {
+        if let Some(user) = self.user.clone().or_else(|| env::var("SUDO_USER").ok()) {
+            config::utils::set_user(&user)?;
+        }
+        Ok(())
+    }
+    /// Path of the configuration file.
+    pub fn path(&self) -> Result<PathBuf, MudrasError> {
+        self.set_user()?;
+        Config::find_path(self.config.as_deref())
+    }
+    pub fn load(&self) -> Result<Config, MudrasError> {
+        self.set_user()?;
+        Config::get(self.config.as_deref())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum CheatsheetFormat {
+    #[default]
+    Md,
+    Html,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum ImportFormat {
+    Sxhkd,
+    Swhkd,
+    Niri,
 }
 
 impl Cli {
@@ -39,13 +175,147 @@ impl Cli {
         utils::set_logger(&cli)?;
 
         match cli.commands {
-            Commands::Run => {
+            Commands::Run { config } => {
                 info!("Running node.");
-                let config = Config::get()?;
-                let mut server = Server::builder().config(config).build().await?;
+                let path = config.path()?;
+                let config = Config::from_file(&path.display().to_string())?;
+                let mut server = Server::builder()
+                    .config(config)
+                    .config_path(path)
+                    .build()
+                    .await?;
                 server.run().await?;
                 Ok(())
             }
+            Commands::Msg(request) => {
+                let response = ipc::send(&request).await?;
+                println!("{}", serde_json::to_string_pretty(&response)?);
+                Ok(())
+            }
+            Commands::Record { file, device } => {
+                record::record(&file, &device).await?;
+                Ok(())
+            }
+            Commands::Replay { file, exec, config } => {
+                let config = config.load()?;
+                let records = record::parse(&fs::read_to_string(&file)?)?;
+                let mut pipeline = Pipeline::new(&config);
+                record::register(&mut pipeline.devices, &records);
+                let mut executor = match exec {
+                    true => Executor::default(),
+                    false => Executor::dry_run(),
+                };
+                executor.set_options(&config.options);
+                let mut source = ReplaySource::new(&records);
+                let mut sinks: Sinks<FakeSink> = Sinks::default();
+                pipeline::replay(&mut pipeline, &mut executor, &mut source, &mut sinks).await?;
+                for (name, sink) in [
+                    ("keyboard", &sinks.keyboard),
+                    ("pointer", &sinks.pointer),
+                    ("switch", &sinks.switch),
+                ] {
+                    for event in &sink.events {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&Record::event(name, 0.0, event))?
+                        );
+                    }
+                }
+                Ok(())
+            }
+            Commands::Simulate { sequence, config } => {
+                let config = config.load()?;
+                let steps = simulate::parse_steps(&sequence)?;
+                for step in simulate::simulate(&config, &steps)? {
+                    print!("{}", step);
+                }
+                Ok(())
+            }
+            Commands::Keys { device, config } => {
+                let config = match config.config {
+                    Some(_) => Some(config.load()?),
+                    None => None,
+                };
+                monitor::monitor(&device, config.as_ref()).await?;
+                Ok(())
+            }
+            Commands::Cheatsheet { format, config } => {
+                let sheet = Cheatsheet::new(&config.load()?);
+                match format {
+                    CheatsheetFormat::Md => print!("{}", sheet.to_markdown()),
+                    CheatsheetFormat::Html => print!("{}", sheet.to_html()),
+                    CheatsheetFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(&sheet)?)
+                    }
+                }
+                Ok(())
+            }
+            Commands::Devices { json, config } => {
+                let config = config.load()?;
+                let reports = inspect::reports(&config);
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&reports)?);
+                } else {
+                    for report in reports {
+                        println!("{}", report);
+                    }
+                }
+                Ok(())
+            }
+            Commands::Fmt {
+                mut files,
+                check,
+                config,
+            } => {
+                if files.is_empty() {
+                    files.push(config.path()?);
+                }
+                let mut unformatted = vec![];
+                for file in files {
+                    let path = file.display().to_string();
+                    let string = fs::read_to_string(&file)?;
+                    let res = fmt::format(&path, &string)?;
+                    // TOML and JSON files are translated, not rewritten.
+                    if !fmt::is_kdl(&path) {
+                        if !check {
+                            print!("{}", res);
+                        }
+                        continue;
+                    }
+                    if res == string {
+                        continue;
+                    }
+                    if check {
+                        println!("{}", file.display());
+                        unformatted.push(file);
+                    } else {
+                        fs::write(&file, res)?;
+                    }
+                }
+                if !unformatted.is_empty() {
+                    let message = format!("{} file(s) are not formatted.", unformatted.len());
+                    let help = "Run mudras fmt on them.";
+                    return Err(LibError::builder().msg(&message).help(help).build().into());
+                }
+                Ok(())
+            }
+            Commands::Schema => {
+                print!("{}", ConfigFile::schema());
+                Ok(())
+            }
+            Commands::Import { from, file } => {
+                let string = fs::read_to_string(&file)?;
+                let import = match from {
+                    ImportFormat::Sxhkd => Import::from_sxhkd(&string),
+                    ImportFormat::Swhkd => Import::from_swhkd(&string),
+                    ImportFormat::Niri => Import::from_niri(&file.display().to_string(), &string)?,
+                };
+                print!("{}", import.to_kdl());
+                for warning in &import.warnings {
+                    eprintln!("warning: {}", warning);
+                }
+                Ok(())
+            }
         }
     }
 }
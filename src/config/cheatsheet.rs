@@ -0,0 +1,222 @@
+/*
+* Cheatsheet of the configured binds.
+*
+* Binds are grouped by submap (main first) and sorted by chord.
+* Printed as markdown or html, or sent as json to on-screen overlays.
+*
+*/
+
+use super::utils::{name_from_key, MODIFIERS};
+use super::{BindArgs, BindSequence, Command, Config, Keyword};
+use crate::input::utils::KeyState;
+
+use evdev::KeyCode;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cheatsheet {
+    pub submaps: Vec<SubmapSheet>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmapSheet {
+    pub name: String,
+    pub binds: Vec<BindSheet>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BindSheet {
+    /// Keys, modifiers first (ex: "Super_L+Enter").
+    pub chord: String,
+    /// "press" or "release".
+    pub trigger: String,
+    pub device: Option<String>,
+    pub repeat: bool,
+    pub swallow: bool,
+    pub description: Option<String>,
+    pub commands: Vec<String>,
+}
+
+impl BindSheet {
+    fn new(sequence: &BindSequence, args: &BindArgs) -> Self {
+        let mut keys: Vec<KeyCode> = sequence.iter().map(|(key, _)| *key).collect();
+        keys.sort_by_key(|key| !MODIFIERS.contains(key));
+        let chord: Vec<String> = keys.into_iter().map(name_from_key).collect();
+        let trigger = match sequence
+            .iter()
+            .any(|(_, state)| state == &KeyState::Released)
+        {
+            true => "release",
+            false => "press",
+        };
+        let commands = args
+            .commands
+            .iter()
+            .map(|e| match e {
+                Command::Sh(e) => e.to_owned(),
+                Command::Internal(Keyword::Enter(e)) => format!("@enter \"{}\"", e),
+                Command::Internal(Keyword::Exit) => "@exit".to_owned(),
+            })
+            .collect();
+        Self {
+            chord: chord.join("+"),
+            trigger: trigger.to_owned(),
+            device: args.device.clone(),
+            repeat: args.repeat,
+            swallow: args.swallow,
+            description: args.description.clone(),
+            commands,
+        }
+    }
+
+    /// Description, or commands when there is none.
+    pub fn action(&self) -> String {
+        match &self.description {
+            Some(e) => e.to_owned(),
+            None => self.commands.join("; "),
+        }
+    }
+
+    /// Repeat, swallow and device flags, comma separated.
+    pub fn flags(&self) -> String {
+        let mut flags = vec![];
+        if self.repeat {
+            flags.push("repeat".to_owned());
+        }
+        if !self.swallow {
+            flags.push("no-swallow".to_owned());
+        }
+        if let Some(device) = &self.device {
+            flags.push(format!("device={}", device));
+        }
+        flags.join(", ")
+    }
+}
+
+impl Cheatsheet {
+    pub fn new(config: &Config) -> Self {
+        let mut submaps: Vec<SubmapSheet> = config
+            .submaps
+            .iter()
+            .map(|(name, submap)| {
+                let scoped = submap.device_binds.values().flat_map(|e| e.iter());
+                let mut binds: Vec<BindSheet> = submap
+                    .binds
+                    .iter()
+                    .chain(scoped)
+                    .map(|(sequence, args)| BindSheet::new(sequence, args))
+                    .collect();
+                binds.sort_by(|a, b| (&a.chord, &a.trigger).cmp(&(&b.chord, &b.trigger)));
+                SubmapSheet {
+                    name: name.to_owned(),
+                    binds,
+                }
+            })
+            .collect();
+        submaps.sort_by_key(|e| (e.name != "main", e.name.clone()));
+        Self { submaps }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut res = String::new();
+        for submap in &self.submaps {
+            let _ = writeln!(res, "## {}\n", submap.name);
+            let _ = writeln!(res, "| Keys | Trigger | Flags | Action |");
+            let _ = writeln!(res, "| --- | --- | --- | --- |");
+            for bind in &submap.binds {
+                let _ = writeln!(
+                    res,
+                    "| `{}` | {} | {} | {} |",
+                    bind.chord,
+                    bind.trigger,
+                    bind.flags(),
+                    bind.action().replace('|', "\\|")
+                );
+            }
+            res.push('\n');
+        }
+        res
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut res = String::from("<!DOCTYPE html>\n<html>\n");
+        res.push_str("<head><meta charset=\"utf-8\"><title>mudras cheatsheet</title></head>\n");
+        res.push_str("<body>\n");
+        for submap in &self.submaps {
+            let _ = writeln!(res, "<h2>{}</h2>", escape_html(&submap.name));
+            res.push_str(
+                "<table>\n<tr><th>Keys</th><th>Trigger</th><th>Flags</th><th>Action</th></tr>\n",
+            );
+            for bind in &submap.binds {
+                let _ = writeln!(
+                    res,
+                    "<tr><td><kbd>{}</kbd></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&bind.chord),
+                    bind.trigger,
+                    escape_html(&bind.flags()),
+                    escape_html(&bind.action())
+                );
+            }
+            res.push_str("</table>\n");
+        }
+        res.push_str("</body>\n</html>\n");
+        res
+    }
+}
+
+fn escape_html(string: &str) -> String {
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miette::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn group_binds_by_submap() -> Result<()> {
+        let kdl = r#"
+            Super+Enter description="Terminal" {
+                @press {
+                    - "kitty"
+                }
+            }
+            Super {
+                @release {
+                    @enter "nav"
+                }
+            }
+            @submap name="nav" {
+                Escape {
+                    @press {
+                        @exit
+                    }
+                }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let sheet = Cheatsheet::new(&config);
+        assert_eq!(
+            vec!["main", "nav"],
+            sheet.submaps.iter().map(|e| &e.name).collect::<Vec<_>>()
+        );
+        let main = &sheet.submaps[0];
+        assert_eq!("Super", main.binds[0].chord);
+        assert_eq!("release", main.binds[0].trigger);
+        assert_eq!("Super+Enter", main.binds[1].chord);
+        assert_eq!("Terminal", main.binds[1].action());
+
+        let markdown = sheet.to_markdown();
+        assert!(markdown.contains("| `Super+Enter` | press |  | Terminal |"));
+        assert!(markdown.contains("| `Escape` | press |  | @exit |"));
+        assert!(sheet.to_html().contains("<td>@enter &quot;nav&quot;</td>"));
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+/*
+* Conflicting binds.
+*
+* Where binds, submaps and options are defined is recorded while decoding a file,
+* in any format, then binds are checked against each other before the file spans are lost.
+*
+* - the same chord bound twice in a submap is an error,
+* - the same keys bound on both press and release,
+*   a submap that can't be left with the chord that enters it,
+*   and a bind on the escape chord are warnings.
+*
+*/
+
+use super::utils::{name_from_key, MODIFIERS};
+use super::{Bind, BindSequence, Command, Keyword, Options};
+use crate::input::utils::KeyState;
+use evdev::KeyCode;
+use std::collections::HashMap;
+
+// Error
+use crate::error::{MudrasError, SpanError, WrapError};
+use miette::{LabeledSpan, NamedSource, Report, Result, Severity, SourceSpan};
+use tracing::{error, warn};
+
+/// Where the binds, submaps and options of a file are defined.
+#[derive(Default, Debug, Clone)]
+pub struct Sources {
+    pub binds: Vec<Definition>,
+    pub submaps: HashMap<String, SourceSpan>,
+    pub options: Option<SourceSpan>,
+}
+
+/// A decoded bind and the node it comes from.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub submap: String,
+    /// Device scope, the submap one if the bind has none.
+    pub device: Option<String>,
+    /// Sorted sequence.
+    pub sequence: BindSequence,
+    pub commands: Vec<Command>,
+    pub span: SourceSpan,
+}
+impl Definition {
+    fn keys(&self) -> Vec<KeyCode> {
+        self.sequence.iter().map(|e| e.0).collect()
+    }
+    fn release(&self) -> bool {
+        self.sequence.iter().any(|e| e.1 == KeyState::Released)
+    }
+    /// Whether triggering the bind leaves the given submap.
+    fn leaves(&self, submap: &str) -> bool {
+        self.commands.iter().any(|e| match e {
+            Command::Internal(Keyword::Exit) => true,
+            Command::Internal(Keyword::Enter(name)) => name != submap,
+            Command::Sh(_) => false,
+        })
+    }
+}
+
+/// Span of a node name.
+pub fn span<S: knus::traits::ErrorSpan>(node: &knus::ast::SpannedNode<S>) -> SourceSpan {
+    node.node_name.span().clone().into()
+}
+
+/// Definitions of the binds decoded from a node, or from a TOML or JSON bind.
+pub fn definitions(
+    submap: &str,
+    device: Option<&str>,
+    binds: &[Bind],
+    span: SourceSpan,
+) -> Vec<Definition> {
+    binds
+        .iter()
+        .map(|bind| {
+            let mut sequence = bind.sequence.clone();
+            sequence.sort_by_key(|a| a.0);
+            Definition {
+                submap: submap.to_owned(),
+                device: bind.args.device.clone().or(device.map(|e| e.to_owned())),
+                sequence,
+                commands: bind.args.commands.clone(),
+                span,
+            }
+        })
+        .collect()
+}
+
+/// Readable chord, modifiers first (ex: "Super_L+Enter").
+fn chord(sequence: &BindSequence) -> String {
+    let mut keys: Vec<KeyCode> = sequence.iter().map(|(key, _)| *key).collect();
+    keys.sort_by_key(|key| !MODIFIERS.contains(key));
+    let keys: Vec<String> = keys.into_iter().map(name_from_key).collect();
+    keys.join("+")
+}
+
+impl Sources {
+    /// Conflicts between the recorded binds.
+    pub fn conflicts(&self, options: &Options) -> Vec<Conflict> {
+        let mut conflicts = vec![];
+        let binds = &self.binds;
+        for (i, a) in binds.iter().enumerate() {
+            for b in &binds[i + 1..] {
+                if a.submap != b.submap || a.device != b.device {
+                    continue;
+                }
+                if a.sequence == b.sequence {
+                    conflicts.push(Conflict {
+                        message: format!(
+                            "{} is bound twice in submap {:#?}.",
+                            chord(&a.sequence),
+                            a.submap
+                        ),
+                        help: "Remove one of the binds, the last one overrides the first.",
+                        severity: Severity::Error,
+                        labels: vec![(a.span, "first bound here"), (b.span, "bound again here")],
+                    });
+                } else if a.keys() == b.keys() && a.release() != b.release() {
+                    let (press, release) = match a.release() {
+                        true => (b, a),
+                        false => (a, b),
+                    };
+                    conflicts.push(Conflict {
+                        message: format!(
+                            "{} is bound on both press and release in submap {:#?}.",
+                            chord(&press.sequence),
+                            a.submap
+                        ),
+                        help: "A single keystroke triggers both binds, keep one of them.",
+                        severity: Severity::Warning,
+                        labels: vec![(press.span, "press bind"), (release.span, "release bind")],
+                    });
+                }
+            }
+        }
+
+        // Submaps must be left with the chord that enters them, or with another bind.
+        for entry in binds {
+            for command in &entry.commands {
+                let Command::Internal(Keyword::Enter(name)) = command else {
+                    continue;
+                };
+                let Some(span) = self.submaps.get(name) else {
+                    continue;
+                };
+                if name == &entry.submap || name == "main" {
+                    continue;
+                }
+                let inner: Vec<&Definition> = binds.iter().filter(|e| &e.submap == name).collect();
+                if !inner.iter().any(|e| e.leaves(name)) {
+                    conflicts.push(Conflict {
+                        message: format!("Submap {:#?} has no bind to leave it.", name),
+                        help: "Add a bind with @exit to the submap.",
+                        severity: Severity::Warning,
+                        labels: vec![(entry.span, "entered here"), (*span, "never left")],
+                    });
+                    continue;
+                }
+                let same_keys: Vec<&&Definition> =
+                    inner.iter().filter(|e| e.keys() == entry.keys()).collect();
+                if let Some(rebind) = same_keys.iter().find(|e| !e.leaves(name)) {
+                    if !same_keys.iter().any(|e| e.leaves(name)) {
+                        conflicts.push(Conflict {
+                            message: format!(
+                                "{} enters submap {:#?}, where it is bound again without leaving it.",
+                                chord(&entry.sequence),
+                                name
+                            ),
+                            help: "Add @exit to the submap bind, or bind another chord.",
+                            severity: Severity::Warning,
+                            labels: vec![
+                                (entry.span, "enters the submap"),
+                                (rebind.span, "doesn't leave the submap"),
+                            ],
+                        });
+                    }
+                }
+            }
+        }
+
+        // The escape chord pauses the daemon before binds are matched.
+        let mut escape = options.escape.clone();
+        escape.sort();
+        for bind in binds {
+            if bind.release() || bind.keys() != escape {
+                continue;
+            }
+            let mut labels = vec![(bind.span, "bound here")];
+            if let Some(span) = self.options {
+                labels.push((span, "escape chord set here"));
+            }
+            conflicts.push(Conflict {
+                message: format!(
+                    "{} is the escape chord, holding it pauses mudras.",
+                    chord(&bind.sequence)
+                ),
+                help: "Bind another chord, or change the escape option.",
+                severity: Severity::Warning,
+                labels,
+            });
+        }
+        conflicts
+    }
+
+    /// Log warnings, and fail on errors.
+    pub fn check(&self, path: &str, string: &str, options: &Options) -> Result<(), MudrasError> {
+        let mut errors = vec![];
+        for conflict in self.conflicts(options) {
+            let severity = conflict.severity;
+            let report = Report::new(conflict.to_error(path, string));
+            match severity {
+                Severity::Error => errors.push(report),
+                _ => warn!("{:?}", report),
+            }
+        }
+        let mut errors = errors.into_iter();
+        let Some(first) = errors.next() else {
+            return Ok(());
+        };
+        for other in errors {
+            error!("{:?}", other);
+        }
+        let message = format!("Couldn't parse configuration file {:#?}.", path);
+        let err = WrapError::builder()
+            .msg(&message)
+            .help("Fix the binds reported above.")
+            .origin(first)
+            .build();
+        Err(err.into())
+    }
+}
+
+/// Binds that conflict, with the places they are defined at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub message: String,
+    pub help: &'static str,
+    pub severity: Severity,
+    pub labels: Vec<(SourceSpan, &'static str)>,
+}
+impl Conflict {
+    fn to_error(&self, path: &str, string: &str) -> SpanError {
+        let labels = self
+            .labels
+            .iter()
+            .map(|(span, label)| LabeledSpan::new_with_span(Some(label.to_string()), *span))
+            .collect();
+        SpanError::builder()
+            .msg(&self.message)
+            .help(self.help)
+            .severity(self.severity)
+            .source_code(NamedSource::new(path, string.to_owned()))
+            .labels(labels)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use miette::{Result, Severity};
+    use pretty_assertions::assert_eq;
+
+    fn messages(kdl: &str) -> Vec<(Severity, String)> {
+        let config: Config = knus::parse("test.kdl", kdl).unwrap();
+        config
+            .sources
+            .conflicts(&config.options)
+            .into_iter()
+            .map(|e| (e.severity, e.message))
+            .collect()
+    }
+
+    #[test]
+    fn detect_conflicts() -> Result<()> {
+        let kdl = r#"
+            @options escape="Ctrl+Alt+Shift+Escape"
+            Super+Enter {
+              @press {
+                - "kitty"
+              }
+            }
+            super+enter {
+              @press {
+                - "foot"
+              }
+            }
+            Super+Enter {
+              @release {
+                - "notify-send hi"
+              }
+            }
+            Super+Enter device="pad" {
+              @press {
+                - "foot"
+              }
+            }
+            Ctrl+Shift+Alt+Escape {
+              @press {
+                - "notify-send escape"
+              }
+            }
+            Super+n {
+              @press {
+                @enter "nav"
+              }
+            }
+            Super+x {
+              @press {
+                @enter "stuck"
+              }
+            }
+            @submap name="nav" {
+              Super+n {
+                @press {
+                  - "notify-send next"
+                }
+              }
+              Escape {
+                @press {
+                  @exit
+                }
+              }
+            }
+            @submap name="stuck" {
+              x {
+                @press {
+                  - "notify-send x"
+                }
+              }
+            }
+        "#;
+        assert_eq!(
+            vec![
+                (
+                    Severity::Error,
+                    r#"Super+Enter is bound twice in submap "main"."#.to_owned()
+                ),
+                (
+                    Severity::Warning,
+                    r#"Super+Enter is bound on both press and release in submap "main"."#
+                        .to_owned()
+                ),
+                (
+                    Severity::Warning,
+                    r#"Super+Enter is bound on both press and release in submap "main"."#
+                        .to_owned()
+                ),
+                (
+                    Severity::Warning,
+                    r#"Super+n enters submap "nav", where it is bound again without leaving it."#
+                        .to_owned()
+                ),
+                (
+                    Severity::Warning,
+                    r#"Submap "stuck" has no bind to leave it."#.to_owned()
+                ),
+                (
+                    Severity::Warning,
+                    "Ctrl+Shift+Alt+Escape is the escape chord, holding it pauses mudras."
+                        .to_owned()
+                ),
+            ],
+            messages(kdl)
+        );
+        assert!(Config::from_kdl("test.kdl", kdl).is_err());
+
+        // The default configuration has no conflict.
+        let kdl = include_str!("../../config.kdl");
+        assert_eq!(Vec::<(Severity, String)>::new(), messages(kdl));
+        Ok(())
+    }
+}
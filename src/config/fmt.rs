@@ -0,0 +1,762 @@
+/*
+* Canonical configuration format.
+*
+* KDL files are formatted from their syntax tree rather than the decoded configuration,
+* so that comments, variables, templates, includes and brace groups are kept.
+* TOML and JSON files have no KDL to keep, they are written from the decoded configuration.
+*
+* - two spaces indentation, one node per line, no ";",
+* - key names spelled like in the documentation (ex: "super" gives "Super"),
+* - modifiers first in binds (Ctrl, Shift, Alt, Super),
+* - properties in a fixed order,
+* - single blank lines between paragraphs.
+*
+*/
+
+use super::utils::{canonical_key_name, key_from_name, name_from_key, MODIFIERS};
+use super::{BindArgs, BindSequence, Command, Config, DeviceFilter, Keyword, Options};
+use super::{Instance, Submap, SwitchBind};
+use crate::input::utils::KeyState;
+
+use knus::ast::{Literal, Radix, SpannedNode, Value};
+use knus::span::Span;
+use std::fmt::Write;
+
+// Error
+use crate::error::{LibError, MudrasError};
+use miette::Result;
+
+type Node = SpannedNode<Span>;
+
+/// Properties are written in this order, unknown ones last by name.
+const PROPERTIES: &[&str] = &[
+    "name",
+    "device",
+    "description",
+    "swallow",
+    "repeat",
+    "exact",
+    "instance",
+    "cooldown",
+    "max-rate",
+    "pause",
+    "phys",
+    "vendor-product",
+    "ignore",
+];
+
+/// Whether a configuration file is written in KDL, rather than TOML or JSON.
+pub fn is_kdl(path: &str) -> bool {
+    !(path.ends_with(".toml") || path.ends_with(".json"))
+}
+
+/// Format a configuration file.
+/// TOML and JSON files are translated to KDL.
+pub fn format(path: &str, string: &str) -> Result<String, MudrasError> {
+    // Refuse to format what the daemon can't read.
+    let config = Config::parse(path, string)?;
+    if !is_kdl(path) {
+        let res = config.to_kdl();
+        check(path, &res)?;
+        return Ok(res);
+    }
+    let document = knus::parse_ast::<Span>(path, string).map_err(|e| {
+        let message = format!("Couldn't parse configuration file {:#?}: {}.", path, e);
+        LibError::builder()
+            .msg(&message)
+            .help("Fix the file.")
+            .build()
+    })?;
+    let mut formatter = Formatter {
+        source: string,
+        res: String::new(),
+    };
+    formatter.nodes(&document.nodes, Context::Config, 0, (0, string.len()), true);
+    let res = formatter.res;
+    check(path, &res)?;
+    Ok(res)
+}
+
+/// Refuse to write a formatted file the daemon can't read.
+fn check(path: &str, res: &str) -> Result<(), MudrasError> {
+    if Config::parse("formatted.kdl", res).is_err() {
+        let message = format!("Formatting {:#?} gives an invalid configuration.", path);
+        let help = "Please report the issue with the file.";
+        return Err(LibError::builder().msg(&message).help(help).build().into());
+    }
+    Ok(())
+}
+
+/// What the nodes of a block describe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    Config,
+    Submap,
+    Other,
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    res: String,
+}
+
+impl Formatter<'_> {
+    /// Write the nodes of a block, and the comments of the block range around them.
+    fn nodes(
+        &mut self,
+        nodes: &[Node],
+        context: Context,
+        depth: usize,
+        range: (usize, usize),
+        mut line_start: bool,
+    ) {
+        let indent = "  ".repeat(depth);
+        let source = self.source;
+        let mut start = range.0;
+        let mut first = true;
+        for node in nodes {
+            let span = node.span();
+            self.gap(&source[start..span.0], &indent, line_start, first);
+            self.node(node, context, depth);
+            first = false;
+            start = span.1;
+            line_start = true;
+        }
+        self.gap(&source[start..range.1], &indent, line_start, first);
+        // No trailing blank lines in blocks.
+        while self.res.ends_with("\n\n") {
+            self.res.pop();
+        }
+    }
+
+    /// Write the comments between two nodes, keeping blank lines that separate paragraphs.
+    fn gap(&mut self, gap: &str, indent: &str, mut line_start: bool, mut first: bool) {
+        let mut rest = gap;
+        loop {
+            let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ';');
+            let newlines = rest[..rest.len() - trimmed.len()].matches('\n').count();
+            if newlines > usize::from(!line_start) && !first {
+                self.res.push('\n');
+            }
+            rest = trimmed;
+            let comment = if rest.starts_with("//") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if rest.starts_with("/*") {
+                block_comment_len(rest)
+            } else if rest.starts_with("/-") {
+                // Commented out node, kept as written.
+                rest.trim_end().len()
+            } else {
+                return;
+            };
+            let _ = writeln!(self.res, "{}{}", indent, rest[..comment].trim_end());
+            rest = &rest[comment..];
+            line_start = false;
+            first = false;
+        }
+    }
+
+    fn node(&mut self, node: &Node, context: Context, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let name = node.node_name.to_string();
+        let is_bind =
+            matches!(context, Context::Config | Context::Submap) && !name.starts_with('@');
+        let release = node.children().any(|e| &**e.node_name == "@release");
+
+        self.res.push_str(&indent);
+        if let Some(type_name) = &node.type_name {
+            let _ = write!(self.res, "({})", kdl_name(type_name));
+        }
+        match is_bind {
+            true => self.res.push_str(&kdl_name(&chord(&name, release))),
+            false => self.res.push_str(&kdl_name(&name)),
+        }
+        for value in &node.arguments {
+            let _ = write!(self.res, " {}", kdl_value(value));
+        }
+        let mut properties: Vec<_> = node.properties.iter().collect();
+        properties.sort_by_key(|(key, _)| property_order(key));
+        for (key, value) in properties {
+            let _ = write!(self.res, " {}={}", kdl_name(key), kdl_value(value));
+        }
+
+        // Header end, to find what follows the children block.
+        let mut end = node.node_name.span().1;
+        for value in node.arguments.iter().chain(node.properties.values()) {
+            end = end.max(value.literal.span().1);
+        }
+        if let Some(children) = &node.children {
+            let span = children.span();
+            let context = match (context, name.as_str()) {
+                (Context::Config, "@submap") => Context::Submap,
+                _ => Context::Other,
+            };
+            self.res.push_str(" {\n");
+            self.nodes(
+                children,
+                context,
+                depth + 1,
+                (span.0 + 1, span.1 - 1),
+                false,
+            );
+            let _ = write!(self.res, "{}}}", indent);
+            end = span.1;
+        }
+        // Trailing comment of the node line.
+        let trailing =
+            self.source[end..node.span().1].trim_matches(|c: char| c.is_whitespace() || c == ';');
+        if !trailing.is_empty() {
+            let _ = write!(self.res, " {}", trailing);
+        }
+        self.res.push('\n');
+    }
+}
+
+/// Position of a property in the canonical order.
+fn property_order(key: &str) -> (usize, String) {
+    let position = PROPERTIES.iter().position(|e| *e == key);
+    (position.unwrap_or(PROPERTIES.len()), key.to_owned())
+}
+
+impl Config {
+    /// KDL of the decoded configuration.
+    /// Variables, templates and brace groups are already expanded.
+    pub fn to_kdl(&self) -> String {
+        let mut res = String::new();
+        for include in &self.includes {
+            let _ = writeln!(res, "@include {}", kdl_string(include));
+        }
+        if self.has_options || self.options != Options::default() {
+            let _ = writeln!(res, "@options{}", options(&self.options));
+        }
+        for device in &self.devices {
+            write_device(&mut res, device);
+        }
+        let mut switches: Vec<&SwitchBind> = self.switches.values().collect();
+        switches.sort_by_key(|e| &e.name);
+        for switch in switches {
+            write_switch(&mut res, switch);
+        }
+        // Main submap binds first, at the top level.
+        let mut submaps: Vec<&Submap> = self.submaps.values().collect();
+        submaps.sort_by_key(|e| (e.name != "main", &e.name));
+        for submap in submaps {
+            if submap.name == "main" {
+                write_binds(&mut res, submap, "");
+                continue;
+            }
+            let mut props = vec![("name", kdl_string(&submap.name))];
+            if let Some(device) = &submap.device {
+                props.push(("device", kdl_string(device)));
+            }
+            if submap.pause {
+                props.push(("pause", "true".to_owned()));
+            }
+            let _ = writeln!(res, "@submap{} {{", properties(props));
+            write_binds(&mut res, submap, "  ");
+            let _ = writeln!(res, "}}");
+        }
+        res
+    }
+}
+
+/// Properties in the canonical order, each preceded by a space.
+fn properties(mut props: Vec<(&str, String)>) -> String {
+    props.sort_by_key(|(key, _)| property_order(key));
+    props
+        .iter()
+        .map(|(key, value)| format!(" {}={}", key, value))
+        .collect()
+}
+
+/// Options that differ from the default ones.
+fn options(options: &Options) -> String {
+    let default = Options::default();
+    let duration =
+        |e: &std::time::Duration| kdl_string(&humantime::format_duration(*e).to_string());
+    let mut props = vec![];
+    if options.max_children != default.max_children {
+        props.push(("max-children", options.max_children.to_string()));
+    }
+    if options.launcher != default.launcher {
+        props.push(("launcher", scalar(options.launcher)));
+    }
+    if options.grab_pointers != default.grab_pointers {
+        props.push(("grab-pointers", options.grab_pointers.to_string()));
+    }
+    if options.merge_devices != default.merge_devices {
+        props.push(("merge-devices", options.merge_devices.to_string()));
+    }
+    if options.escape != default.escape {
+        let names: Vec<String> = options.escape.iter().map(|e| name_from_key(*e)).collect();
+        props.push(("escape", kdl_string(&chord(&names.join("+"), false))));
+    }
+    if options.escape_hold != default.escape_hold {
+        props.push(("escape-hold", duration(&options.escape_hold)));
+    }
+    if let Some(e) = &options.stuck_key_timeout {
+        props.push(("stuck-key-timeout", duration(e)));
+    }
+    if let Some(e) = &options.pause_timeout {
+        props.push(("pause-timeout", duration(e)));
+    }
+    properties(props)
+}
+
+/// KDL value of a single word enum variant (ex: Instance::Single gives "single").
+fn scalar(value: impl std::fmt::Debug) -> String {
+    kdl_string(&format!("{:?}", value).to_lowercase())
+}
+
+fn write_device(res: &mut String, device: &DeviceFilter) {
+    let mut props = vec![];
+    if let Some(e) = &device.device_name {
+        props.push(("name", kdl_string(e)));
+    }
+    if let Some((vendor, product)) = device.vendor_product {
+        let ids = format!("{:04x}:{:04x}", vendor, product);
+        props.push(("vendor-product", kdl_string(&ids)));
+    }
+    if let Some(e) = &device.phys {
+        props.push(("phys", kdl_string(e)));
+    }
+    if device.ignore {
+        props.push(("ignore", "true".to_owned()));
+    }
+    if let Some(e) = device.grab {
+        props.push(("grab", e.to_string()));
+    }
+    if let Some(e) = &device.seat {
+        props.push(("seat", kdl_string(e)));
+    }
+    let _ = write!(
+        res,
+        "@device {}{}",
+        kdl_string(&device.name),
+        properties(props)
+    );
+    if device.udev.is_empty() {
+        res.push('\n');
+        return;
+    }
+    res.push_str(" {\n");
+    for (key, value) in &device.udev {
+        let _ = writeln!(res, "  udev {}={}", kdl_name(key), kdl_string(value));
+    }
+    res.push_str("}\n");
+}
+
+fn write_switch(res: &mut String, switch: &SwitchBind) {
+    // Both states share the switch properties.
+    let props = switch
+        .on
+        .as_ref()
+        .or(switch.off.as_ref())
+        .map(bind_properties)
+        .unwrap_or_default();
+    let _ = writeln!(
+        res,
+        "@switch {}{} {{",
+        kdl_string(&switch.name),
+        properties(props)
+    );
+    for (state, args) in [("@on", &switch.on), ("@off", &switch.off)] {
+        if let Some(args) = args {
+            let _ = writeln!(res, "  {} {{", state);
+            write_commands(res, &args.commands, "    ");
+            let _ = writeln!(res, "  }}");
+        }
+    }
+    let _ = writeln!(res, "}}");
+}
+
+/// Properties shared by binds and switches, when not the default ones.
+fn bind_properties(args: &BindArgs) -> Vec<(&'static str, String)> {
+    let mut props = vec![];
+    if let Some(e) = &args.description {
+        props.push(("description", kdl_string(e)));
+    }
+    if args.instance != Instance::default() {
+        props.push(("instance", scalar(args.instance)));
+    }
+    if let Some(e) = &args.cooldown {
+        let cooldown = humantime::format_duration(*e).to_string();
+        props.push(("cooldown", kdl_string(&cooldown)));
+    }
+    if let Some(e) = args.max_rate {
+        props.push(("max-rate", e.to_string()));
+    }
+    props
+}
+
+/// Binds of a submap, sorted by chord.
+/// The device scope is only written when it isn't the submap one.
+fn write_binds(res: &mut String, submap: &Submap, indent: &str) {
+    let mut binds: Vec<((String, bool), &BindArgs)> = submap
+        .binds
+        .iter()
+        .chain(submap.device_binds.values().flatten())
+        .map(|(sequence, args)| (sequence_chord(sequence), args))
+        .collect();
+    binds.sort_by(|a, b| (&a.0, &a.1.device).cmp(&(&b.0, &b.1.device)));
+    for ((chord, release), args) in binds {
+        let mut props = bind_properties(args);
+        if let Some(device) = args
+            .device
+            .as_ref()
+            .filter(|_| args.device != submap.device)
+        {
+            props.push(("device", kdl_string(device)));
+        }
+        if !args.swallow {
+            props.push(("swallow", "false".to_owned()));
+        }
+        let _ = writeln!(
+            res,
+            "{}{}{} {{",
+            indent,
+            kdl_name(&chord),
+            properties(props)
+        );
+        let trigger = match (release, args.repeat) {
+            (true, _) => "@release",
+            (false, true) => "@press repeat=true",
+            (false, false) => "@press",
+        };
+        let _ = writeln!(res, "{}  {} {{", indent, trigger);
+        write_commands(res, &args.commands, &format!("{}    ", indent));
+        let _ = writeln!(res, "{}  }}", indent);
+        let _ = writeln!(res, "{}}}", indent);
+    }
+}
+
+/// Chord of a bind sequence, and whether it is triggered on release.
+fn sequence_chord(sequence: &BindSequence) -> (String, bool) {
+    let names: Vec<String> = sequence
+        .iter()
+        .map(|(key, _)| name_from_key(*key))
+        .collect();
+    let release = sequence
+        .last()
+        .is_some_and(|(_, state)| state == &KeyState::Released);
+    (chord(&names.join("+"), release), release)
+}
+
+/// One command node per line.
+pub fn write_commands(res: &mut String, commands: &[Command], indent: &str) {
+    for command in commands {
+        let _ = match command {
+            Command::Sh(e) => writeln!(res, "{}- {}", indent, kdl_string(e)),
+            Command::Internal(Keyword::Enter(e)) => {
+                writeln!(res, "{}@enter {}", indent, kdl_string(e))
+            }
+            Command::Internal(Keyword::Exit) => writeln!(res, "{}@exit", indent),
+        };
+    }
+}
+
+/// Length of a (nested) block comment.
+fn block_comment_len(string: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < string.len() {
+        if string[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if string[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += string[i..].chars().next().map(char::len_utf8).unwrap_or(1);
+        }
+    }
+    string.len()
+}
+
+/// Bind name with canonical key names, modifiers first.
+/// The released key of release binds stays last, brace groups are left in place.
+fn chord(name: &str, release: bool) -> String {
+    let mut keys: Vec<String> = vec![];
+    let mut token = String::new();
+    let mut res = String::new();
+    for c in name.chars().chain(['+']) {
+        if !matches!(c, '+' | '{' | '}' | ',' | '-') {
+            token.push(c);
+            continue;
+        }
+        let key = canonical_key_name(&token).unwrap_or(token.clone());
+        token.clear();
+        if name.contains('{') {
+            res.push_str(&key);
+            res.push(c);
+        } else {
+            keys.push(key);
+        }
+    }
+    if name.contains('{') {
+        res.pop();
+        return res;
+    }
+    let last = match release {
+        true => keys.pop(),
+        false => None,
+    };
+    let modifier = |key: &String| {
+        key_from_name(key)
+            .and_then(|e| MODIFIERS.iter().position(|m| *m == e))
+            .unwrap_or(MODIFIERS.len())
+    };
+    keys.sort_by_key(modifier);
+    keys.extend(last);
+    keys.join("+")
+}
+
+fn kdl_value(value: &Value<Span>) -> String {
+    let literal = match &*value.literal {
+        Literal::Null => "null".to_owned(),
+        Literal::Bool(e) => e.to_string(),
+        Literal::Int(e) => {
+            let (sign, digits) = match e.1.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", &*e.1),
+            };
+            let prefix = match e.0 {
+                Radix::Bin => "0b",
+                Radix::Oct => "0o",
+                Radix::Dec => "",
+                Radix::Hex => "0x",
+            };
+            format!("{}{}{}", sign, prefix, digits)
+        }
+        Literal::Decimal(e) => e.0.to_string(),
+        Literal::String(e) => kdl_string(e),
+    };
+    match &value.type_name {
+        Some(e) => format!("({}){}", kdl_name(e), literal),
+        None => literal,
+    }
+}
+
+/// KDL string, raw when it holds quotes, backslashes or new lines.
+pub fn kdl_string(string: &str) -> String {
+    if !string.contains(['"', '\\', '\n']) {
+        return format!("{:?}", string);
+    }
+    let mut hashes = "#".to_owned();
+    while string.contains(&format!("\"{}", hashes)) {
+        hashes.push('#');
+    }
+    format!("r{}\"{}\"{}", hashes, string, hashes)
+}
+
+/// KDL identifier, quoted unless plain.
+pub fn kdl_name(name: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_+-@.".contains(c);
+    let numeric = name.starts_with(|c: char| c.is_ascii_digit())
+        || (name.starts_with(['-', '+']) && name[1..].starts_with(|c: char| c.is_ascii_digit()));
+    match !name.is_empty() && name.chars().all(plain) && !numeric {
+        true => name.to_owned(),
+        false => kdl_string(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn format_config() -> Result<()> {
+        let kdl = r##"
+// Applications
+
+
+super+enter swallow=false description="Terminal" { @press { - "kitty"; }; } // main terminal
+shift+super+n {
+    /* notes */
+    @press {
+      - r#"notify-send "hi""#
+    }
+}
+a+SUPER {
+  @release {
+    @enter "nav"
+  }
+}
+@submap name="nav" {
+  escape {
+    @press {
+      @exit
+    }
+  }
+
+  "Super+{1-3}" {
+    @press {
+      - "niri msg action focus-workspace {1-3}"
+    }
+  }
+}
+"##;
+        let res = format("test.kdl", kdl)?;
+        assert_eq!(
+            r##"// Applications
+
+Super+Enter description="Terminal" swallow=false {
+  @press {
+    - "kitty"
+  }
+} // main terminal
+Shift+Super+n {
+  /* notes */
+  @press {
+    - r#"notify-send "hi""#
+  }
+}
+a+Super {
+  @release {
+    @enter "nav"
+  }
+}
+@submap name="nav" {
+  Escape {
+    @press {
+      @exit
+    }
+  }
+
+  "Super+{1-3}" {
+    @press {
+      - "niri msg action focus-workspace {1-3}"
+    }
+  }
+}
+"##,
+            res
+        );
+        // Formatting is stable.
+        assert_eq!(res, format("test.kdl", &res)?);
+        Ok(())
+    }
+
+    #[test]
+    fn encode_config() -> Result<()> {
+        let kdl = r#"
+            @options launcher="systemd" stuck-key-timeout="30s"
+            @device "pad" vendor-product="1234:abcd" {
+              udev ID_INPUT_TABLET="1"
+            }
+            @switch "lid" instance="single" {
+              @on {
+                - "swaylock"
+              }
+            }
+            @vars {
+              term "kitty"
+            }
+            super+enter cooldown="1s" {
+              @press {
+                - "${term}"
+              }
+            }
+            "Super+{1-2}" {
+              @press repeat=true {
+                - "niri msg action focus-workspace {1-2}"
+              }
+            }
+            Super device="pad" swallow=false {
+              @release {
+                @enter "nav"
+              }
+            }
+            @submap name="nav" device="pad" pause=true {
+              escape {
+                @press {
+                  @exit
+                }
+              }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let res = config.to_kdl();
+        assert_eq!(
+            r#"@options launcher="systemd" stuck-key-timeout="30s"
+@device "pad" vendor-product="1234:abcd" {
+  udev ID_INPUT_TABLET="1"
+}
+@switch "lid" instance="single" {
+  @on {
+    - "swaylock"
+  }
+}
+Super device="pad" swallow=false {
+  @release {
+    @enter "nav"
+  }
+}
+Super+1 {
+  @press repeat=true {
+    - "niri msg action focus-workspace 1"
+  }
+}
+Super+2 {
+  @press repeat=true {
+    - "niri msg action focus-workspace 2"
+  }
+}
+Super+Enter cooldown="1s" {
+  @press {
+    - "kitty"
+  }
+}
+@submap name="nav" device="pad" pause=true {
+  Escape {
+    @press {
+      @exit
+    }
+  }
+}
+"#,
+            res
+        );
+        // Encoding the decoded configuration gives it back.
+        assert_eq!(res, Config::from_kdl("test.kdl", &res)?.to_kdl());
+        Ok(())
+    }
+
+    #[test]
+    fn format_toml() -> Result<()> {
+        let toml = r#"
+include = ["base.kdl"]
+
+[[binds]]
+keys = "Super"
+trigger = "release"
+enter = "nav"
+
+[[submaps]]
+name = "nav"
+binds = [{ keys = "Escape", exit = true }]
+"#;
+        assert_eq!(
+            r#"@include "base.kdl"
+Super {
+  @release {
+    @enter "nav"
+  }
+}
+@submap name="nav" {
+  Escape {
+    @press {
+      @exit
+    }
+  }
+}
+"#,
+            format("test.toml", toml)?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,634 @@
+/*
+* Import keybindings from other hotkey daemons.
+*
+* sxhkd and swhkd files:
+* - chords are translated key by key, brace groups are kept as is,
+* - "@" (release) and "~" (do not swallow) prefixes become @release and swallow=false,
+* - chord chains ("super + w ; h") and swhkd modes become submaps.
+*
+* niri files:
+* - binds of the `binds {}` block,
+* - `spawn` and `spawn-sh` become shell commands,
+* - other actions become `niri msg action` commands.
+*
+* What can't be translated is reported, not guessed.
+*
+*/
+
+use super::fmt::{kdl_name, kdl_string, write_commands};
+use super::utils::{key_from_name, name_from_key};
+use super::{Command, Keyword};
+
+use std::fmt::Write;
+
+// Error
+use crate::error::{MudrasError, WrapError};
+use miette::{Report, Result};
+
+/// Key names of X keysyms and niri, lowercase, with their configuration name.
+/// Other names are looked up as configuration or evdev names.
+const KEY_ALIASES: &[(&str, &str)] = &[
+    ("super", "Super"),
+    ("mod4", "Super"),
+    ("mod", "Super"),
+    ("control", "Ctrl"),
+    ("ctrl", "Ctrl"),
+    ("shift", "Shift"),
+    ("alt", "Alt"),
+    ("mod1", "Alt"),
+    ("meta", "Alt"),
+    ("mod5", "AltGr"),
+    ("iso_level3_shift", "AltGr"),
+    ("return", "Enter"),
+    ("prior", "KEY_PAGEUP"),
+    ("page_up", "KEY_PAGEUP"),
+    ("next", "KEY_PAGEDOWN"),
+    ("page_down", "KEY_PAGEDOWN"),
+    ("print", "KEY_SYSRQ"),
+    ("period", "KEY_DOT"),
+    ("bracketleft", "KEY_LEFTBRACE"),
+    ("bracketright", "KEY_RIGHTBRACE"),
+    ("xf86audioraisevolume", "KEY_VOLUMEUP"),
+    ("xf86audiolowervolume", "KEY_VOLUMEDOWN"),
+    ("xf86audiomute", "KEY_MUTE"),
+    ("xf86audiomicmute", "KEY_MICMUTE"),
+    ("xf86audioplay", "KEY_PLAYPAUSE"),
+    ("xf86audiopause", "KEY_PAUSECD"),
+    ("xf86audiostop", "KEY_STOPCD"),
+    ("xf86audionext", "KEY_NEXTSONG"),
+    ("xf86audioprev", "KEY_PREVIOUSSONG"),
+    ("xf86monbrightnessup", "brightness_up"),
+    ("xf86monbrightnessdown", "brightness_down"),
+    ("button1", "btn_left"),
+    ("button2", "btn_middle"),
+    ("button3", "btn_right"),
+    ("button4", "WheelUp"),
+    ("button5", "WheelDown"),
+    ("button8", "btn_side"),
+    ("button9", "btn_extra"),
+    ("wheelscrollup", "WheelUp"),
+    ("wheelscrolldown", "WheelDown"),
+    ("wheelscrollleft", "WheelLeft"),
+    ("wheelscrollright", "WheelRight"),
+];
+
+/// A translated configuration.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Import {
+    /// The main submap first.
+    pub submaps: Vec<ImportedSubmap>,
+    /// What couldn't be translated, with the line or bind it comes from.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ImportedSubmap {
+    pub name: String,
+    pub binds: Vec<ImportedBind>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedBind {
+    /// Chord in configuration syntax (ex: "Super+{h,l}").
+    pub chord: String,
+    pub release: bool,
+    pub repeat: bool,
+    pub swallow: bool,
+    pub cooldown: Option<String>,
+    pub description: Option<String>,
+    pub commands: Vec<Command>,
+}
+impl Default for ImportedBind {
+    fn default() -> Self {
+        Self {
+            chord: String::new(),
+            release: false,
+            repeat: false,
+            swallow: true,
+            cooldown: None,
+            description: None,
+            commands: vec![],
+        }
+    }
+}
+
+impl Import {
+    fn new() -> Self {
+        Self {
+            submaps: vec![ImportedSubmap {
+                name: "main".to_owned(),
+                binds: vec![],
+            }],
+            warnings: vec![],
+        }
+    }
+
+    /// Binds of a submap, created if needed.
+    fn submap(&mut self, name: &str) -> &mut Vec<ImportedBind> {
+        let index = match self.submaps.iter().position(|e| e.name == name) {
+            Some(index) => index,
+            None => {
+                self.submaps.push(ImportedSubmap {
+                    name: name.to_owned(),
+                    binds: vec![],
+                });
+                self.submaps.len() - 1
+            }
+        };
+        &mut self.submaps[index].binds
+    }
+
+    /// Translate a chord, reporting unknown keys.
+    fn chord(&mut self, line: usize, chord: &str) -> String {
+        let mut res = String::new();
+        let mut token = String::new();
+        for c in chord.chars().chain([' ']) {
+            if !matches!(c, '+' | '{' | '}' | ',' | '-') && !c.is_whitespace() {
+                token.push(c);
+                continue;
+            }
+            if !token.is_empty() {
+                match translate_key(&token) {
+                    Some(e) => res.push_str(&e),
+                    None => {
+                        self.warnings
+                            .push(format!("line {}: unknown key {:?}.", line, token));
+                        res.push_str(&token);
+                    }
+                }
+                token.clear();
+            }
+            if !c.is_whitespace() {
+                res.push(c);
+            }
+        }
+        res
+    }
+
+    /// Translate an sxhkd file.
+    pub fn from_sxhkd(string: &str) -> Self {
+        Self::from_hotkeys(string, false)
+    }
+
+    /// Translate a swhkd file.
+    pub fn from_swhkd(string: &str) -> Self {
+        Self::from_hotkeys(string, true)
+    }
+
+    fn from_hotkeys(string: &str, swhkd: bool) -> Self {
+        let mut import = Self::new();
+        // Current swhkd mode and whether it exits after a bind.
+        let mut mode: Option<(String, bool)> = None;
+        let mut chord: Option<(usize, String)> = None;
+        for (line, text) in logical_lines(string) {
+            let trimmed = text.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if text.starts_with(char::is_whitespace) {
+                match chord.take() {
+                    Some((line, chord)) => {
+                        let submap = mode.as_ref().map(|e| e.0.as_str()).unwrap_or("main");
+                        let oneoff = mode.as_ref().is_some_and(|e| e.1);
+                        import.hotkey(line, submap, &chord, trimmed, oneoff);
+                    }
+                    None => import
+                        .warnings
+                        .push(format!("line {}: command without chord.", line)),
+                }
+                continue;
+            }
+            let (keyword, rest) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+            match keyword {
+                "mode" if swhkd => {
+                    let mut words = rest.split_whitespace();
+                    let name = words.next().unwrap_or_default().to_owned();
+                    let mut oneoff = false;
+                    for option in words {
+                        match option {
+                            "oneoff" => oneoff = true,
+                            _ => import.warnings.push(format!(
+                                "line {}: mode option {:?} has no equivalent.",
+                                line, option
+                            )),
+                        }
+                    }
+                    import.submap(&name);
+                    mode = Some((name, oneoff));
+                }
+                "endmode" if swhkd => mode = None,
+                "include" | "ignore" | "unbind" if swhkd => import.warnings.push(format!(
+                    "line {}: {:?} is not translated{}.",
+                    line,
+                    trimmed,
+                    match keyword {
+                        "include" => ", import the file separately and @include it",
+                        _ => "",
+                    }
+                )),
+                _ => chord = Some((line, trimmed.to_owned())),
+            }
+        }
+        if let Some((line, _)) = chord {
+            import
+                .warnings
+                .push(format!("line {}: chord without command.", line));
+        }
+        import
+    }
+
+    /// Add a hotkey, chord chains entering submaps.
+    fn hotkey(&mut self, line: usize, submap: &str, chord: &str, command: &str, oneoff: bool) {
+        let locked = chord.ends_with(':');
+        let chain: Vec<&str> = chord.trim_end_matches(':').split(';').collect();
+        let mut submap = submap.to_owned();
+        let mut prefix = vec![];
+        for (i, chord) in chain.iter().enumerate() {
+            let mut bind = ImportedBind::default();
+            let mut chord = chord.trim().to_owned();
+            if chord.contains('~') {
+                bind.swallow = false;
+                chord = chord.replace('~', "");
+            }
+            if chord.contains('@') {
+                bind.release = true;
+                chord = chord.replace('@', "");
+            }
+            bind.chord = self.chord(line, &chord);
+            prefix.push(bind.chord.clone());
+            if i + 1 < chain.len() {
+                // Enter the chain submap, Escape aborts the chain.
+                let next = prefix.join(" ; ");
+                bind.commands = vec![Command::Internal(Keyword::Enter(next.clone()))];
+                push_bind(self.submap(&submap), bind);
+                if !self.submap(&next).iter().any(|e| e.chord == "Escape") {
+                    self.submap(&next).push(ImportedBind {
+                        chord: "Escape".to_owned(),
+                        commands: vec![Command::Internal(Keyword::Exit)],
+                        ..Default::default()
+                    });
+                }
+                submap = next;
+            } else {
+                bind.commands = commands(command);
+                let exits = bind.commands.last() == Some(&Command::Internal(Keyword::Exit));
+                if ((chain.len() > 1 && !locked) || oneoff) && !exits {
+                    bind.commands.push(Command::Internal(Keyword::Exit));
+                }
+                push_bind(self.submap(&submap), bind);
+            }
+        }
+    }
+
+    /// Translate the `binds {}` block of a niri file.
+    pub fn from_niri(path: &str, string: &str) -> Result<Self, MudrasError> {
+        let document = knus::parse_ast::<knus::span::LineSpan>(path, string).map_err(|e| {
+            let message = format!("Couldn't parse niri file {:#?}.", path);
+            let help = "Fix the errors reported above.";
+            WrapError::builder()
+                .msg(&message)
+                .help(help)
+                .origin(Report::new(e))
+                .build()
+        })?;
+        let mut import = Self::new();
+        let mut found = false;
+        for node in document.nodes.iter().filter(|e| &**e.node_name == "binds") {
+            found = true;
+            for node in node.children() {
+                import.niri_bind(node);
+            }
+        }
+        if !found {
+            import
+                .warnings
+                .push("no `binds {}` block found.".to_owned());
+        }
+        Ok(import)
+    }
+
+    fn niri_bind(&mut self, node: &knus::ast::SpannedNode<knus::span::LineSpan>) {
+        let name = node.node_name.to_string();
+        let mut bind = ImportedBind {
+            // niri repeats binds by default.
+            repeat: true,
+            ..Default::default()
+        };
+        let line = line_of(node);
+        bind.chord = self.chord(line, &name);
+        for (key, val) in &node.properties {
+            match (&***key, &*val.literal) {
+                ("repeat", knus::ast::Literal::Bool(e)) => bind.repeat = *e,
+                ("cooldown-ms", knus::ast::Literal::Int(e)) => {
+                    bind.cooldown = Some(format!("{}ms", e.1))
+                }
+                ("hotkey-overlay-title", knus::ast::Literal::String(e)) => {
+                    bind.description = Some(e.to_string())
+                }
+                ("hotkey-overlay-title", knus::ast::Literal::Null) => {}
+                (key, _) => self.warnings.push(format!(
+                    "line {}: bind {:?} property {:?} has no equivalent.",
+                    line, name, key
+                )),
+            }
+        }
+        for action in node.children() {
+            let args: Vec<String> = action.arguments.iter().map(literal).collect();
+            let command = match &*action.node_name.to_string() {
+                "spawn" => args
+                    .iter()
+                    .map(|e| shell_quote(e))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                "spawn-sh" => args.join(" "),
+                name => {
+                    let mut words = vec!["niri msg action".to_owned(), name.to_owned()];
+                    for (key, val) in &action.properties {
+                        match &*val.literal {
+                            knus::ast::Literal::Bool(true) => words.push(format!("--{}", &***key)),
+                            _ => {
+                                words.push(format!("--{}={}", &***key, shell_quote(&literal(val))))
+                            }
+                        }
+                    }
+                    words.extend(args.iter().map(|e| shell_quote(e)));
+                    words.join(" ")
+                }
+            };
+            bind.commands.push(Command::Sh(command));
+        }
+        if bind.commands.is_empty() {
+            self.warnings
+                .push(format!("line {}: bind {:?} has no action.", line, name));
+        }
+        push_bind(self.submap("main"), bind);
+    }
+
+    /// Configuration in KDL.
+    pub fn to_kdl(&self) -> String {
+        let mut res = String::new();
+        for submap in &self.submaps {
+            if submap.name == "main" {
+                for bind in &submap.binds {
+                    write_bind(&mut res, bind, "");
+                }
+            } else {
+                let _ = writeln!(res, "@submap name={} {{", kdl_string(&submap.name));
+                for bind in &submap.binds {
+                    write_bind(&mut res, bind, "  ");
+                }
+                let _ = writeln!(res, "}}");
+            }
+        }
+        res
+    }
+}
+
+/// Add a bind, the last definition of a chord wins.
+fn push_bind(binds: &mut Vec<ImportedBind>, bind: ImportedBind) {
+    binds.retain(|e| (&e.chord, e.release) != (&bind.chord, bind.release));
+    binds.push(bind);
+}
+
+/// Lines joined on trailing backslashes, with the number of their first line.
+fn logical_lines(string: &str) -> Vec<(usize, String)> {
+    let mut res: Vec<(usize, String)> = vec![];
+    let mut continued = false;
+    for (i, line) in string.lines().enumerate() {
+        let (line, next) = match line.strip_suffix('\\') {
+            Some(e) => (e, true),
+            None => (line, false),
+        };
+        match (continued, res.last_mut()) {
+            (true, Some((_, last))) => last.push_str(line.trim_start()),
+            _ => res.push((i + 1, line.to_owned())),
+        }
+        continued = next;
+    }
+    res
+}
+
+/// Commands of an sxhkd or swhkd hotkey.
+/// swhkd "@enter mode" and "@escape" may be chained with "&&".
+fn commands(command: &str) -> Vec<Command> {
+    let mut res = vec![];
+    let mut shell: Vec<&str> = vec![];
+    for part in command.split("&&") {
+        let keyword = match part.trim().split_once(' ') {
+            Some(("@enter", mode)) => {
+                Some(Command::Internal(Keyword::Enter(mode.trim().to_owned())))
+            }
+            _ if part.trim() == "@escape" => Some(Command::Internal(Keyword::Exit)),
+            _ => None,
+        };
+        match keyword {
+            Some(keyword) => {
+                if !shell.is_empty() {
+                    res.push(Command::Sh(shell.join(" && ")));
+                    shell.clear();
+                }
+                res.push(keyword);
+            }
+            None => shell.push(part.trim()),
+        }
+    }
+    if !shell.is_empty() {
+        res.push(Command::Sh(shell.join(" && ")));
+    }
+    res
+}
+
+/// Configuration name of a key, if known.
+fn translate_key(name: &str) -> Option<String> {
+    if name == "_" {
+        return Some(name.to_owned());
+    }
+    let lower = name.to_lowercase();
+    if let Some((_, e)) = KEY_ALIASES.iter().find(|(e, _)| *e == lower) {
+        return Some(e.to_string());
+    }
+    key_from_name(name)
+        .or_else(|| key_from_name(&format!("KEY_{}", name)))
+        .map(name_from_key)
+}
+
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+fn literal(value: &knus::ast::Value<knus::span::LineSpan>) -> String {
+    match &*value.literal {
+        knus::ast::Literal::Null => "null".to_owned(),
+        knus::ast::Literal::Bool(e) => e.to_string(),
+        knus::ast::Literal::Int(e) => e.1.to_string(),
+        knus::ast::Literal::Decimal(e) => e.0.to_string(),
+        knus::ast::Literal::String(e) => e.to_string(),
+    }
+}
+
+fn line_of(node: &knus::ast::SpannedNode<knus::span::LineSpan>) -> usize {
+    node.span().0.line + 1
+}
+
+fn write_bind(res: &mut String, bind: &ImportedBind, indent: &str) {
+    let mut props = String::new();
+    if !bind.swallow {
+        props.push_str(" swallow=false");
+    }
+    if let Some(cooldown) = &bind.cooldown {
+        let _ = write!(props, " cooldown={}", kdl_string(cooldown));
+    }
+    if let Some(description) = &bind.description {
+        let _ = write!(props, " description={}", kdl_string(description));
+    }
+    let _ = writeln!(res, "{}{}{} {{", indent, kdl_name(&bind.chord), props);
+    let trigger = match (bind.release, bind.repeat) {
+        (true, _) => "@release",
+        (false, true) => "@press repeat=true",
+        (false, false) => "@press",
+    };
+    let _ = writeln!(res, "{}  {} {{", indent, trigger);
+    write_commands(res, &bind.commands, &format!("{}    ", indent));
+    let _ = writeln!(res, "{}  }}", indent);
+    let _ = writeln!(res, "{}}}", indent);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn import_sxhkd() -> Result<()> {
+        let sxhkd = r#"
+# terminal
+super + Return
+    kitty
+
+super + {_,shift + }{h,l}
+    bspc node -{f,s} {west,east}
+
+~@Print
+    maim "${HOME}/shot.png"
+
+super + w ; {h,l}
+    bspc node -f {west,east}
+"#;
+        let import = Import::from_sxhkd(sxhkd);
+        assert_eq!(Vec::<String>::new(), import.warnings);
+        let main = &import.submaps[0];
+        assert_eq!("Super+Enter", main.binds[0].chord);
+        assert_eq!("Super+{_,Shift+}{h,l}", main.binds[1].chord);
+        assert_eq!("KEY_SYSRQ", main.binds[2].chord);
+        assert!(main.binds[2].release && !main.binds[2].swallow);
+        // Shell parameters are written as is.
+        assert_eq!(
+            vec![Command::Sh(r#"maim "${HOME}/shot.png""#.to_owned())],
+            main.binds[2].commands
+        );
+        assert_eq!(
+            vec![Command::Internal(Keyword::Enter("Super+w".to_owned()))],
+            main.binds[3].commands
+        );
+        assert_eq!(
+            vec![
+                Command::Sh("bspc node -f {west,east}".to_owned()),
+                Command::Internal(Keyword::Exit)
+            ],
+            import.submaps[1].binds[1].commands
+        );
+
+        let config = Config::from_kdl("test.kdl", &import.to_kdl())?;
+        assert_eq!(7, config.submaps["main"].binds.len());
+        assert_eq!(3, config.submaps["Super+w"].binds.len());
+        Ok(())
+    }
+
+    #[test]
+    fn import_swhkd() -> Result<()> {
+        let swhkd = r#"
+super + m
+    notify-send music && @enter music
+
+mode music oneoff
+p
+    mpc toggle
+escape
+    @escape
+endmode
+
+include ~/.config/swhkd/extra
+"#;
+        let import = Import::from_swhkd(swhkd);
+        assert_eq!(1, import.warnings.len());
+        assert_eq!(
+            vec![
+                Command::Sh("notify-send music".to_owned()),
+                Command::Internal(Keyword::Enter("music".to_owned()))
+            ],
+            import.submaps[0].binds[0].commands
+        );
+        let music = &import.submaps[1];
+        assert_eq!("music", music.name);
+        assert_eq!(
+            vec![
+                Command::Sh("mpc toggle".to_owned()),
+                Command::Internal(Keyword::Exit)
+            ],
+            music.binds[0].commands
+        );
+        Config::from_kdl("test.kdl", &import.to_kdl())?;
+        Ok(())
+    }
+
+    #[test]
+    fn import_niri() -> Result<()> {
+        let niri = r#"
+input {
+    keyboard {
+        xkb {
+            layout "us"
+        }
+    }
+}
+binds {
+    Mod+T hotkey-overlay-title="Open a Terminal" { spawn "alacritty"; }
+    Mod+Shift+E { spawn "sh" "-c" "notify-send \"$USER\""; }
+    Mod+Q repeat=false { close-window; }
+    Mod+1 { focus-workspace 1; }
+    XF86AudioRaiseVolume allow-when-locked=true { spawn-sh "wpctl set-volume @DEFAULT_AUDIO_SINK@ 0.1+"; }
+    Print { screenshot show-pointer=false; }
+}
+"#;
+        let import = Import::from_niri("config.kdl", niri)?;
+        assert_eq!(1, import.warnings.len());
+        let binds = &import.submaps[0].binds;
+        assert_eq!("Super+t", binds[0].chord);
+        assert_eq!(Some("Open a Terminal".to_owned()), binds[0].description);
+        assert_eq!(
+            vec![Command::Sh(r#"sh -c 'notify-send "$USER"'"#.to_owned())],
+            binds[1].commands
+        );
+        assert!(!binds[2].repeat);
+        assert_eq!(
+            vec![Command::Sh("niri msg action focus-workspace 1".to_owned())],
+            binds[3].commands
+        );
+        assert_eq!("KEY_VOLUMEUP", binds[4].chord);
+        assert_eq!(
+            vec![Command::Sh(
+                "niri msg action screenshot --show-pointer=false".to_owned()
+            )],
+            binds[5].commands
+        );
+        let config = Config::from_kdl("test.kdl", &import.to_kdl())?;
+        assert_eq!(6, config.submaps["main"].binds.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+/*
+* Configuration includes and fragments.
+*
+* Precedence, from lowest to highest:
+* - files included with `@include` (in order, glob matches sorted by name),
+* - the including file,
+* - fragments of the `config.d` directory next to the main file (sorted by name).
+*
+* A file of higher precedence overrides binds, switches and device filters
+* of the same name, and options if it sets any.
+* Submaps of the same name are merged bind by bind.
+*
+*/
+
+use super::utils::{self, name_from_key};
+use super::{BindSequence, Binds, Config, Submap};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Error
+use crate::error::{LibError, MudrasError, WrapError};
+use miette::{Error, Result};
+use tracing::{trace, warn};
+
+impl Config {
+    /// Parse a file and the files it includes.
+    /// Stack holds the files being included, to detect cycles.
+    pub(super) fn load(
+        path: &str,
+        string: &str,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<Self, MudrasError> {
+        let mut config = Self::parse(path, string)?;
+        let mut base: Option<Config> = None;
+        for pattern in std::mem::take(&mut config.includes) {
+            for file in resolve(path, &pattern)? {
+                let included = Self::load_file(&file, stack).map_err(|e| {
+                    let message = format!("Couldn't include {:#?} from {:#?}.", file, path);
+                    let help = "Fix the included file or the @include node.";
+                    WrapError::builder()
+                        .msg(&message)
+                        .help(help)
+                        .origin(Error::from_err(e))
+                        .build()
+                })?;
+                match &mut base {
+                    Some(base) => base.merge(included, &file.display().to_string()),
+                    None => base = Some(included),
+                }
+            }
+        }
+        match base {
+            Some(mut base) => {
+                base.merge(config, path);
+                Ok(base)
+            }
+            None => Ok(config),
+        }
+    }
+
+    /// Read and parse a file and the files it includes.
+    pub(super) fn load_file(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Self, MudrasError> {
+        let canonical = path.canonicalize()?;
+        if stack.contains(&canonical) {
+            let cycle: Vec<String> = stack
+                .iter()
+                .chain([&canonical])
+                .map(|e| e.display().to_string())
+                .collect();
+            let message = format!("Include cycle: {}.", cycle.join(" -> "));
+            let help = "Remove one of the @include nodes of the cycle.";
+            return Err(LibError::builder().msg(&message).help(help).build().into());
+        }
+        trace!("loading config file {:?}", path);
+        let string = fs::read_to_string(path)?;
+        stack.push(canonical);
+        let config = Self::load(&path.display().to_string(), &string, stack);
+        stack.pop();
+        config
+    }
+
+    /// Merge a configuration of higher precedence into this one.
+    pub fn merge(&mut self, other: Config, path: &str) {
+        for (name, submap) in other.submaps {
+            match self.submaps.get_mut(&name) {
+                Some(e) => e.merge(submap, path),
+                None => {
+                    self.submaps.insert(name, submap);
+                }
+            }
+        }
+        for (code, switch) in other.switches {
+            if let Some(prev) = self.switches.insert(code, switch.clone()) {
+                if prev != switch {
+                    warn!("switch {:#?} is overridden by {:#?}.", switch.name, path);
+                }
+            }
+        }
+        for filter in other.devices {
+            if let Some(prev) = self.devices.iter_mut().find(|e| e.name == filter.name) {
+                if *prev != filter {
+                    warn!("device {:#?} is overridden by {:#?}.", filter.name, path);
+                }
+                *prev = filter;
+            } else {
+                self.devices.push(filter);
+            }
+        }
+        if other.has_options {
+            if self.has_options && self.options != other.options {
+                warn!("options are overridden by {:#?}.", path);
+            }
+            self.options = other.options;
+            self.has_options = true;
+        }
+    }
+}
+
+impl Submap {
+    /// Merge a submap of higher precedence into this one.
+    fn merge(&mut self, other: Submap, path: &str) {
+        let name = self.name.clone();
+        let merge_binds = |binds: &mut Binds, other: Binds| {
+            for (sequence, args) in other {
+                if let Some(prev) = binds.insert(sequence.clone(), args.clone()) {
+                    if prev != args {
+                        warn!(
+                            "bind {:#?} of submap {:#?} is overridden by {:#?}.",
+                            chord(&sequence),
+                            name,
+                            path
+                        );
+                    }
+                }
+            }
+        };
+        merge_binds(&mut self.binds, other.binds);
+        for (scope, binds) in other.device_binds {
+            merge_binds(self.device_binds.entry(scope).or_default(), binds);
+        }
+        self.device = other.device.or(self.device.take());
+        self.pause |= other.pause;
+    }
+}
+
+fn chord(sequence: &BindSequence) -> String {
+    let keys: Vec<String> = sequence
+        .iter()
+        .map(|(key, _)| name_from_key(*key))
+        .collect();
+    keys.join("+")
+}
+
+/// Files matching an include pattern, relative to the including file.
+/// Plain paths must exist, glob patterns may match nothing.
+fn resolve(including: &str, pattern: &str) -> Result<Vec<PathBuf>, MudrasError> {
+    let dir = Path::new(including).parent().unwrap_or(Path::new("."));
+    let is_glob = pattern.contains(['*', '?', '[']);
+    if !is_glob {
+        let path = dir.join(utils::expand_tilde(pattern));
+        let path = utils::shellexpand(&path.display().to_string())?;
+        return Ok(vec![PathBuf::from(path)]);
+    }
+    let pattern = dir.join(utils::expand_tilde(pattern));
+    glob_files(&pattern.display().to_string())
+}
+
+/// Files matching a glob pattern, sorted by name.
+pub(super) fn glob_files(pattern: &str) -> Result<Vec<PathBuf>, MudrasError> {
+    let paths = glob::glob(pattern).map_err(|e| {
+        let message = format!("Couldn't parse include pattern {:#?}: {}.", pattern, e);
+        let help = "Use shell like wildcards (ex: \"binds/*.kdl\").";
+        LibError::builder().msg(&message).help(help).build()
+    })?;
+    let mut files: Vec<PathBuf> = paths
+        .filter_map(|e| e.ok())
+        .filter(|e| e.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Command;
+    use crate::input::modifier;
+    use crate::input::utils::KeyState;
+    use evdev::KeyCode;
+    use miette::IntoDiagnostic;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    /// Write files to a fresh temporary directory, removed when dropped.
+    fn write_files(files: &[(&str, &str)]) -> Result<TempDir> {
+        let dir = TempDir::with_prefix("mudras-").into_diagnostic()?;
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).into_diagnostic()?;
+            fs::write(path, content).into_diagnostic()?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn include_files_and_fragments() -> Result<()> {
+        let dir = write_files(&[
+            (
+                "config.kdl",
+                r#"
+                    @include "team/*.kdl"
+                    Super+Enter {
+                        @press {
+                            - "foot"
+                        }
+                    }
+                    "#,
+            ),
+            (
+                "team/base.kdl",
+                r#"
+                    @options max-children=12
+                    Super+Enter {
+                        @press {
+                            - "kitty"
+                        }
+                    }
+                    Super+Space {
+                        @press {
+                            - "yofi"
+                        }
+                    }
+                    "#,
+            ),
+            (
+                "config.d/10-local.kdl",
+                r#"
+                    Super+Space {
+                        @press {
+                            - "fuzzel"
+                        }
+                    }
+                    "#,
+            ),
+        ])?;
+        let config = Config::from_file(&dir.path().join("config.kdl").display().to_string())?;
+        let main = config.submaps.get("main").unwrap();
+        let command = |key: KeyCode| {
+            let mut sequence = vec![
+                (modifier::SUPER, KeyState::Pressed),
+                (key, KeyState::Pressed),
+            ];
+            sequence.sort_by_key(|a| a.0);
+            main.binds.get(&sequence).unwrap().commands.clone()
+        };
+        // The including file overrides the included one.
+        assert_eq!(
+            vec![Command::Sh("foot".to_owned())],
+            command(KeyCode::KEY_ENTER)
+        );
+        // Fragments override the main file.
+        assert_eq!(
+            vec![Command::Sh("fuzzel".to_owned())],
+            command(KeyCode::KEY_SPACE)
+        );
+        assert_eq!(12, config.options.max_children);
+        Ok(())
+    }
+
+    #[test]
+    fn detect_include_cycles() -> Result<()> {
+        let dir = write_files(&[
+            ("a.kdl", r#"@include "b.kdl""#),
+            ("b.kdl", r#"@include "a.kdl""#),
+        ])?;
+        let res = Config::from_file(&dir.path().join("a.kdl").display().to_string());
+        assert!(res.is_err());
+        Ok(())
+    }
+}
@@ -8,25 +8,38 @@
 *
 */
 
-use super::utils::{self, get_modifiers};
+use super::conflicts::{self, Definition, Sources};
+use super::utils;
+use super::vars;
+use crate::input::modifier;
 use crate::input::utils::KeyState;
-use evdev::KeyCode;
+use evdev::{KeyCode, SwitchCode};
 // Config
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 
 // Parse
 use knus::errors::DecodeError;
-use knus::{Decode, DecodeChildren};
+use knus::Decode;
 
 // Error
-use crate::error::{MudrasError, WrapError};
 use miette::Result;
-use tracing::{error, trace};
+use tracing::warn;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub submaps: Submaps,
+    pub options: Options,
+    pub devices: Vec<DeviceFilter>,
+    pub switches: Switches,
+    /// Files to include (paths or glob patterns, as written).
+    pub includes: Vec<String>,
+    /// Whether options are set by the file, rather than defaulted.
+    pub has_options: bool,
+    /// Where binds are defined, for conflict checks.
+    pub sources: Sources,
 }
 
 impl<S> knus::DecodeChildren<S> for Config
@@ -40,51 +53,416 @@ where
         // Create a main submap
         let mut main: Submap = Submap {
             name: "main".to_owned(),
-            mods: vec![],
-            binds: Binds::new(),
+            ..Default::default()
         };
-        let mut raw_binds: Binds = HashMap::new();
-        let mut binds: Binds = HashMap::new();
+        let mut binds: Vec<Bind> = vec![];
 
         let mut submaps = Submaps::new();
+        let mut options = Options::default();
+        let mut devices: Vec<DeviceFilter> = vec![];
+        let mut switches = Switches::new();
+        let mut includes = vec![];
+        let mut has_options = false;
+        let mut sources = Sources::default();
 
         for node in nodes {
             match &*node.node_name.to_string() {
+                "@include" => {
+                    includes.push(parse_arg_node("@include", node, ctx)?);
+                }
+                "@vars" => {
+                    vars::decode_vars(node, ctx)?;
+                }
+                "@template" => {
+                    vars::decode_template(node, ctx)?;
+                }
+                "@options" => {
+                    options = Options::decode_node(node, ctx)?;
+                    has_options = true;
+                    sources.options = Some(conflicts::span(node));
+                }
+                "@device" => {
+                    devices.push(DeviceFilter::decode_node(node, ctx)?);
+                }
                 "@submap" => {
                     let submap = Submap::decode_node(node, ctx)?;
+                    let definitions = ctx.get::<Vec<Definition>>();
+                    sources
+                        .binds
+                        .extend(definitions.into_iter().flatten().cloned());
+                    sources
+                        .submaps
+                        .insert(submap.name.clone(), conflicts::span(node));
                     submaps.insert(submap.name.clone(), submap);
                 }
+                "@switch" => {
+                    let switch = SwitchBind::decode_node(node, ctx)?;
+                    switches.insert(switch.switch, switch);
+                }
                 _ => {
-                    // Modifiers
-                    let bind = Bind::decode_node(node, ctx)?;
-                    raw_binds.insert(bind.sequence, bind.args);
-
-                    // Binds
-                    let bind = Bind::decode_node(node, ctx)?;
-                    // Sort sequence
-                    let mut sequence = bind.sequence.clone();
-                    sequence.sort_by(|a, b| a.0.cmp(&b.0));
-                    binds.insert(sequence, bind.args);
+                    let expanded = Bind::decode_expanded(node, ctx)?;
+                    let span = conflicts::span(node);
+                    let definitions = conflicts::definitions(&main.name, None, &expanded, span);
+                    sources.binds.extend(definitions);
+                    binds.extend(expanded);
                 }
             };
         }
         // Main submap
-        let mods = get_modifiers(&raw_binds).unwrap();
-        main.mods = mods;
-        main.binds = binds;
-
+        main.insert_binds(binds);
         submaps.insert(main.name.clone(), main);
-        Ok(Self { submaps })
+
+        Ok(Self {
+            submaps,
+            options,
+            devices,
+            switches,
+            includes,
+            has_options,
+            sources,
+        })
     }
 }
 
+impl Config {
+    /// Warn about inconsistencies once every file is merged.
+    pub fn check(&self) {
+        // Device scopes must refer to a device filter.
+        for submap in self.submaps.values() {
+            for scope in submap.device_binds.keys() {
+                if !self.devices.iter().any(|e| &e.name == scope) {
+                    warn!(
+                        "submap {:#?} uses an undefined device scope {:#?}.",
+                        submap.name, scope
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Matches input devices by their properties.
+/// Patterns accept shell like wildcards.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct DeviceFilter {
+    /// Filter name, used as a device scope by submaps and binds.
+    pub name: String,
+    /// Device name pattern.
+    pub device_name: Option<String>,
+    /// Vendor and product ids.
+    pub vendor_product: Option<(u16, u16)>,
+    /// Physical path pattern.
+    pub phys: Option<String>,
+    /// Udev properties the device must have.
+    pub udev: Vec<(String, String)>,
+    /// Never grab matching devices.
+    pub ignore: bool,
+    /// Whether matching pointer devices are grabbed.
+    /// Default to the `grab-pointers` option.
+    pub grab: Option<bool>,
+    /// Group of devices whose keys are merged together for bind matching.
+    pub seat: Option<String>,
+}
+impl<S> knus::Decode<S> for DeviceFilter
+where
+    S: knus::traits::ErrorSpan,
+{
+    fn decode_node(
+        node: &knus::ast::SpannedNode<S>,
+        ctx: &mut knus::decode::Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        let val = node
+            .arguments
+            .first()
+            .ok_or_else(|| DecodeError::missing(node, "additional argument `name` is required"))?;
+        let mut filter = DeviceFilter {
+            name: knus::traits::DecodeScalar::decode(val, ctx)?,
+            ..Default::default()
+        };
+        for (key, val) in &node.properties {
+            match &***key {
+                "name" => {
+                    let pattern: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    if let Err(e) = glob::Pattern::new(&pattern) {
+                        ctx.emit_error(DecodeError::conversion(&val.literal, e));
+                    }
+                    filter.device_name = Some(pattern);
+                }
+                "phys" => {
+                    let pattern: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    if let Err(e) = glob::Pattern::new(&pattern) {
+                        ctx.emit_error(DecodeError::conversion(&val.literal, e));
+                    }
+                    filter.phys = Some(pattern);
+                }
+                "vendor-product" => {
+                    let ids: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    match utils::parse_vendor_product(&ids) {
+                        Ok(v) => filter.vendor_product = Some(v),
+                        Err(e) => ctx.emit_error(DecodeError::conversion(&val.literal, e)),
+                    }
+                }
+                "ignore" => {
+                    filter.ignore = knus::traits::DecodeScalar::decode(val, ctx)?;
+                }
+                "grab" => {
+                    filter.grab = Some(knus::traits::DecodeScalar::decode(val, ctx)?);
+                }
+                "seat" => {
+                    filter.seat = Some(knus::traits::DecodeScalar::decode(val, ctx)?);
+                }
+                _ => {}
+            }
+        }
+        for child in node.children() {
+            match &*child.node_name.to_string() {
+                "udev" => {
+                    for (key, val) in &child.properties {
+                        let value: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                        filter.udev.push((key.to_string(), value));
+                    }
+                }
+                _ => {
+                    ctx.emit_error(DecodeError::unexpected(
+                        child,
+                        "node",
+                        "only udev is accepted",
+                    ));
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// Daemon wide options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Maximum number of spawned processes alive at the same time.
+    /// Default to 128.
+    pub max_children: usize,
+    /// How commands are spawned.
+    /// Default to child.
+    pub launcher: Launcher,
+    /// Grab pointer devices so that mouse buttons and wheel can be bound.
+    /// Default to false.
+    pub grab_pointers: bool,
+    /// Merge keys of every device that isn't part of a seat group,
+    /// so that a modifier held on a device applies to keys of another.
+    /// Default to true.
+    pub merge_devices: bool,
+    /// Chord that ungrabs every device and pauses bind matching (or resumes).
+    /// Default to Ctrl+Alt+Shift+Escape.
+    pub escape: Vec<KeyCode>,
+    /// How long the escape chord must be held.
+    /// Default to 2s.
+    pub escape_hold: Duration,
+    /// Release keys, modifiers aside, that haven't been pressed or repeated for that long.
+    /// Disabled by default, "0s" disables the watchdog too.
+    pub stuck_key_timeout: Option<Duration>,
+    /// Resume automatically after being paused for that long.
+    pub pause_timeout: Option<Duration>,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_children: 128,
+            launcher: Launcher::default(),
+            grab_pointers: false,
+            merge_devices: true,
+            escape: vec![
+                modifier::CTRL,
+                modifier::ALT,
+                modifier::SHIFT,
+                KeyCode::KEY_ESC,
+            ],
+            escape_hold: Duration::from_secs(2),
+            stuck_key_timeout: None,
+            pause_timeout: None,
+        }
+    }
+}
+
+impl<S> knus::Decode<S> for Options
+where
+    S: knus::traits::ErrorSpan,
+{
+    fn decode_node(
+        node: &knus::ast::SpannedNode<S>,
+        ctx: &mut knus::decode::Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        let mut options = Options::default();
+        for (key, val) in &node.properties {
+            match &***key {
+                "max-children" => {
+                    options.max_children = knus::traits::DecodeScalar::decode(val, ctx)?;
+                }
+                "launcher" => {
+                    options.launcher = knus::traits::DecodeScalar::decode(val, ctx)?;
+                }
+                "grab-pointers" => {
+                    options.grab_pointers = knus::traits::DecodeScalar::decode(val, ctx)?;
+                }
+                "merge-devices" => {
+                    options.merge_devices = knus::traits::DecodeScalar::decode(val, ctx)?;
+                }
+                "escape" => {
+                    let chord: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    match utils::bind_to_keys(&chord, &SequenceType::Press) {
+                        Ok(keys) => {
+                            options.escape = keys.into_iter().map(|e| e.0).collect();
+                        }
+                        Err(e) => ctx.emit_error(DecodeError::conversion(&val.literal, e)),
+                    }
+                }
+                "escape-hold" => {
+                    let duration: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    match humantime::parse_duration(&duration) {
+                        Ok(v) => options.escape_hold = v,
+                        Err(e) => ctx.emit_error(DecodeError::conversion(&val.literal, e)),
+                    };
+                }
+                "stuck-key-timeout" => {
+                    let duration: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    match humantime::parse_duration(&duration) {
+                        Ok(v) => options.stuck_key_timeout = (!v.is_zero()).then_some(v),
+                        Err(e) => ctx.emit_error(DecodeError::conversion(&val.literal, e)),
+                    };
+                }
+                "pause-timeout" => {
+                    let duration: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    match humantime::parse_duration(&duration) {
+                        Ok(v) => options.pause_timeout = Some(v),
+                        Err(e) => ctx.emit_error(DecodeError::conversion(&val.literal, e)),
+                    };
+                }
+                _ => {}
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    knus::DecodeScalar,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Launcher {
+    /// Spawned processes are children of the daemon.
+    #[default]
+    Child,
+    /// Each spawned process gets its own transient systemd user scope.
+    Systemd,
+    /// Spawned processes are detached from the daemon.
+    Detach,
+}
+
 pub type Submaps = HashMap<String, Submap>;
 
 #[derive(Default, Clone, Debug)]
 pub struct Submap {
     pub name: String,
-    pub mods: Vec<KeyCode>,
+    /// Binds that apply to every device.
     pub binds: Binds,
+    /// Default device scope of the submap binds.
+    pub device: Option<String>,
+    /// Binds that only apply to a device scope.
+    pub device_binds: HashMap<String, Binds>,
+    /// Pause matching while the submap is active:
+    /// keys are forwarded unless they match one of its binds exactly.
+    pub pause: bool,
+}
+impl Submap {
+    /// Store a bind with the unscoped binds or with its device scope binds.
+    pub fn insert(&mut self, sequence: BindSequence, mut args: BindArgs) {
+        if args.device.is_none() {
+            args.device = self.device.clone();
+        }
+        match args.device.clone() {
+            Some(device) => {
+                self.device_binds
+                    .entry(device)
+                    .or_default()
+                    .insert(sequence, args);
+            }
+            None => {
+                self.binds.insert(sequence, args);
+            }
+        }
+    }
+    /// Store decoded binds.
+    pub fn insert_binds(&mut self, binds: Vec<Bind>) {
+        for bind in binds {
+            // Sort sequence
+            let mut sequence = bind.sequence;
+            sequence.sort_by_key(|a| a.0);
+            self.insert(sequence, bind.args);
+        }
+    }
+    /// Binds of a device scope, or unscoped binds.
+    pub fn scope(&self, device: Option<&str>) -> Option<&Binds> {
+        match device {
+            Some(device) => self.device_binds.get(device),
+            None => Some(&self.binds),
+        }
+    }
+    /// Find the bind matching a sequence typed on a device of the given scope,
+    /// with its own sequence.
+    /// Binds of the device scope take precedence over unscoped ones,
+    /// and side specific modifiers over modifiers of either side.
+    pub fn find<'a>(
+        &'a self,
+        sequence: &BindSequence,
+        device: Option<&'a str>,
+    ) -> Option<(Option<&'a str>, &'a BindSequence, &'a BindArgs)> {
+        let variants = modifier::variants(sequence);
+        if let Some((sequence, args)) = device
+            .and_then(|e| self.device_binds.get(e))
+            .and_then(|binds| variants.iter().find_map(|e| binds.get_key_value(e)))
+        {
+            return Some((device, sequence, args));
+        }
+        variants
+            .iter()
+            .find_map(|e| self.binds.get_key_value(e))
+            .map(|(sequence, args)| (None, sequence, args))
+    }
+    /// Whether the pressed keys of a sequence are on their way to a bind:
+    /// they are part of the bind, and only its triggers are left to press.
+    pub fn is_prefix(&self, sequence: &BindSequence, device: Option<&str>) -> bool {
+        let pressed: Vec<KeyCode> = sequence
+            .iter()
+            .filter(|(_, state)| state == &KeyState::Pressed)
+            .map(|(key, _)| *key)
+            .collect();
+        let scoped = device.and_then(|e| self.device_binds.get(e));
+        let mut binds = self
+            .binds
+            .keys()
+            .chain(scoped.into_iter().flat_map(|e| e.keys()));
+        binds.any(|bind| {
+            let keys: Vec<KeyCode> = bind.iter().map(|(key, _)| *key).collect();
+            let triggers = modifier::triggers(&keys);
+            let left: Vec<&KeyCode> = keys
+                .iter()
+                .filter(|key| !pressed.iter().any(|e| modifier::matches(**key, *e)))
+                .collect();
+            let part = pressed
+                .iter()
+                .all(|e| keys.iter().any(|key| modifier::matches(*key, *e)));
+            part && !left.is_empty() && left.iter().all(|e| triggers.contains(e))
+        })
+    }
 }
 impl<S> knus::Decode<S> for Submap
 where
@@ -95,35 +473,40 @@ where
         ctx: &mut knus::decode::Context<S>,
     ) -> Result<Self, DecodeError<S>> {
         // Global props
-        let mut name: String = "".to_owned();
+        let mut submap = Submap::default();
         for (key, val) in &node.properties {
             match &***key {
                 "name" => {
-                    name = knus::traits::DecodeScalar::decode(val, ctx)?;
+                    submap.name = knus::traits::DecodeScalar::decode(val, ctx)?;
+                }
+                "device" => {
+                    submap.device = Some(knus::traits::DecodeScalar::decode(val, ctx)?);
+                }
+                "pause" => {
+                    submap.pause = knus::traits::DecodeScalar::decode(val, ctx)?;
                 }
                 _ => {}
-            };
+            }
         }
-        // Modifiers
-        let mut raw_binds: Binds = HashMap::new();
+        let mut binds: Vec<Bind> = vec![];
+        let mut definitions = vec![];
         for node in node.children() {
-            let bind = Bind::decode_node(node, ctx)?;
-            raw_binds.insert(bind.sequence, bind.args);
+            let expanded = Bind::decode_expanded(node, ctx)?;
+            let device = submap.device.as_deref();
+            let span = conflicts::span(node);
+            definitions.extend(conflicts::definitions(
+                &submap.name,
+                device,
+                &expanded,
+                span,
+            ));
+            binds.extend(expanded);
         }
-        let mods = get_modifiers(&raw_binds).unwrap();
-
-        // Binds
-        let mut binds: Binds = HashMap::new();
-        for node in node.children() {
-            let bind = Bind::decode_node(node, ctx)?;
-            // Sort sequence
-            let mut sequence = bind.sequence.clone();
-            sequence.sort_by(|a, b| a.0.cmp(&b.0));
+        submap.insert_binds(binds);
+        // Collected by the configuration, for conflict checks.
+        ctx.set(definitions);
 
-            binds.insert(sequence, bind.args);
-        }
-
-        Ok(Submap { name, mods, binds })
+        Ok(submap)
     }
 }
 
@@ -156,6 +539,45 @@ pub struct BindArgs {
     /// Do not trigger release action when too much key pressed.
     /// Default to true.
     pub exact: bool,
+    /// What to do with processes spawned by a previous trigger.
+    /// Default to multiple.
+    pub instance: Instance,
+    /// Minimum delay between two triggers.
+    pub cooldown: Option<Duration>,
+    /// Maximum number of triggers per second.
+    pub max_rate: Option<u32>,
+    /// Device scope the bind is restricted to.
+    pub device: Option<String>,
+    /// Human description, shown in cheatsheets instead of commands.
+    pub description: Option<String>,
+}
+
+/// Policy applied when a bind is triggered
+/// while processes it previously spawned are still alive.
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    knus::DecodeScalar,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Instance {
+    /// Always spawn a new process.
+    #[default]
+    Multiple,
+    /// Do not spawn anything while a previous process is alive.
+    Single,
+    /// Kill the previous process if alive, spawn a new one otherwise.
+    Toggle,
+    /// Kill the previous process if alive, then spawn a new one.
+    Restart,
 }
 
 impl<S> knus::Decode<S> for Bind
@@ -172,38 +594,35 @@ where
             swallow: true,
             repeat: false,
             exact: true,
+            instance: Instance::default(),
+            cooldown: None,
+            max_rate: None,
+            device: None,
+            description: None,
             commands: vec![],
         };
 
         // Sequence Global args
-        for (name, val) in &node.properties {
-            match &***name {
-                "swallow" => {
-                    args.swallow = knus::traits::DecodeScalar::decode(val, ctx)?;
-                }
-                _ => {}
-            }
-        }
+        decode_bind_properties(node, ctx, &mut args)?;
 
         // Bind sequence
-        let mut sequence = vec![];
+        let mut sequence_type = None;
         for child in node.children() {
             let name: String = child.node_name.to_string();
             match &*name {
                 "@press" => {
-                    sequence =
-                        utils::bind_to_keys(&node.node_name.to_string(), &SequenceType::Press)
-                            .unwrap();
+                    sequence_type = Some(SequenceType::Press);
                     let mut iter_args = node.arguments.iter();
                     if let Some(val) = iter_args.next() {
                         args.repeat = knus::traits::DecodeScalar::decode(val, ctx)?
                     };
+                    if let Some(val) = child.properties.get("repeat") {
+                        args.repeat = knus::traits::DecodeScalar::decode(val, ctx)?
+                    }
                     args.commands = children_to_commands(child, ctx)?;
                 }
                 "@release" => {
-                    sequence =
-                        utils::bind_to_keys(&node.node_name.to_string(), &SequenceType::Release)
-                            .unwrap();
+                    sequence_type = Some(SequenceType::Release);
                     args.commands = children_to_commands(child, ctx)?;
                 }
                 _ => {
@@ -215,11 +634,196 @@ where
                 }
             };
         }
+        // Of the first alternative for names with brace groups.
+        let name = &utils::expand_braces(&node.node_name)[0];
+        let sequence = match sequence_type.map(|e| utils::bind_to_keys(name, &e)) {
+            Some(Ok(e)) => e,
+            Some(Err(e)) => {
+                ctx.emit_error(DecodeError::conversion(&node.node_name, e));
+                vec![]
+            }
+            None => vec![],
+        };
 
         Ok(Self { sequence, args })
     }
 }
 
+impl Bind {
+    /// Decode a bind node, with one bind per alternative of the name brace groups
+    /// (ex: "Super+{1-9}").
+    pub fn decode_expanded<S: knus::traits::ErrorSpan>(
+        node: &knus::ast::SpannedNode<S>,
+        ctx: &mut knus::decode::Context<S>,
+    ) -> Result<Vec<Self>, DecodeError<S>> {
+        let bind = Self::decode_node(node, ctx)?;
+        // Unknown keys are already reported.
+        if bind.sequence.is_empty() {
+            return Ok(vec![]);
+        }
+        match bind.expand(&node.node_name) {
+            Ok(binds) => Ok(binds),
+            Err(e) => {
+                ctx.emit_error(DecodeError::conversion(&node.node_name, e));
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// One bind per alternative of the name brace groups.
+    /// Brace groups of commands and description are expanded alongside,
+    /// they must have as many alternatives as the name, or none.
+    pub fn expand(self, name: &str) -> Result<Vec<Self>, String> {
+        let names = utils::expand_braces(name);
+        let count = names.len();
+        if count == 1 {
+            return Ok(vec![self]);
+        }
+        let sequence_type = match self
+            .sequence
+            .iter()
+            .any(|(_, state)| state == &KeyState::Released)
+        {
+            true => SequenceType::Release,
+            false => SequenceType::Press,
+        };
+        let expand = |string: &str| {
+            let res = utils::expand_braces(string);
+            match res.len() {
+                1 => Ok(vec![res[0].clone(); count]),
+                n if n == count => Ok(res),
+                n => Err(format!(
+                    "{:?} has {} alternatives, but the bind name has {}",
+                    string, n, count
+                )),
+            }
+        };
+        let mut commands: Vec<Vec<Command>> = vec![];
+        for command in &self.args.commands {
+            commands.push(match command {
+                Command::Sh(e) => expand(e)?.into_iter().map(Command::Sh).collect(),
+                Command::Internal(Keyword::Enter(e)) => expand(e)?
+                    .into_iter()
+                    .map(|e| Command::Internal(Keyword::Enter(e)))
+                    .collect(),
+                Command::Internal(Keyword::Exit) => vec![command.clone(); count],
+            });
+        }
+        let descriptions = match &self.args.description {
+            Some(e) => Some(expand(e)?),
+            None => None,
+        };
+
+        let mut binds = vec![];
+        for (i, name) in names.iter().enumerate() {
+            let mut args = self.args.clone();
+            args.commands = commands.iter().map(|e| e[i].clone()).collect();
+            if let Some(descriptions) = &descriptions {
+                args.description = Some(descriptions[i].clone());
+            }
+            binds.push(Self {
+                sequence: utils::bind_to_keys(name, &sequence_type).map_err(|e| e.to_string())?,
+                args,
+            });
+        }
+        Ok(binds)
+    }
+}
+
+/// Properties shared by binds and switches.
+fn decode_bind_properties<S: knus::traits::ErrorSpan>(
+    node: &knus::ast::SpannedNode<S>,
+    ctx: &mut knus::decode::Context<S>,
+    args: &mut BindArgs,
+) -> Result<(), DecodeError<S>> {
+    for (name, val) in &node.properties {
+        match &***name {
+            "swallow" => {
+                args.swallow = knus::traits::DecodeScalar::decode(val, ctx)?;
+            }
+            "instance" => {
+                args.instance = knus::traits::DecodeScalar::decode(val, ctx)?;
+            }
+            "cooldown" => {
+                let duration: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+                match humantime::parse_duration(&duration) {
+                    Ok(v) => args.cooldown = Some(v),
+                    Err(e) => ctx.emit_error(DecodeError::conversion(&val.literal, e)),
+                };
+            }
+            "max-rate" => {
+                args.max_rate = Some(knus::traits::DecodeScalar::decode(val, ctx)?);
+            }
+            "device" => {
+                args.device = Some(knus::traits::DecodeScalar::decode(val, ctx)?);
+            }
+            "description" => {
+                args.description = Some(knus::traits::DecodeScalar::decode(val, ctx)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub type Switches = HashMap<SwitchCode, SwitchBind>;
+
+/// Commands triggered when a switch (lid, tablet mode, jack...) changes state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchBind {
+    pub name: String,
+    pub switch: SwitchCode,
+    pub on: Option<BindArgs>,
+    pub off: Option<BindArgs>,
+}
+impl<S> knus::Decode<S> for SwitchBind
+where
+    S: knus::traits::ErrorSpan,
+{
+    fn decode_node(
+        node: &knus::ast::SpannedNode<S>,
+        ctx: &mut knus::decode::Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        let val = node
+            .arguments
+            .first()
+            .ok_or_else(|| DecodeError::missing(node, "additional argument `name` is required"))?;
+        let name: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+        let switch = match utils::switch_from_name(&name) {
+            Ok(v) => v,
+            Err(e) => return Err(DecodeError::conversion(&val.literal, e)),
+        };
+
+        let mut args = BindArgs::default();
+        decode_bind_properties(node, ctx, &mut args)?;
+
+        let mut bind = SwitchBind {
+            name,
+            switch,
+            on: None,
+            off: None,
+        };
+        for child in node.children() {
+            let args = BindArgs {
+                commands: children_to_commands(child, ctx)?,
+                ..args.clone()
+            };
+            match &*child.node_name.to_string() {
+                "@on" => bind.on = Some(args),
+                "@off" => bind.off = Some(args),
+                _ => {
+                    ctx.emit_error(DecodeError::unexpected(
+                        child,
+                        "node",
+                        "only @on and @off are accepted",
+                    ));
+                }
+            }
+        }
+        Ok(bind)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Command {
     /// To be executed from a terminal
@@ -243,15 +847,19 @@ pub fn children_to_commands<S: knus::traits::ErrorSpan>(
         if child.node_name.to_string() == "-" {
             // Common bash instruction.
             let cmd: String = parse_arg_node("-", child, ctx)?;
-            commands.push(Command::Sh(cmd));
+            commands.push(Command::Sh(vars::expand_arg(&cmd, ctx)));
         } else if child.node_name.to_string() == "@enter" {
             // Special keyword for entering submap.
             let value = child.arguments.first().unwrap();
-            let submap_name = knus::traits::DecodeScalar::decode(value, ctx)?;
+            let submap_name: String = knus::traits::DecodeScalar::decode(value, ctx)?;
+            let submap_name = vars::expand_arg(&submap_name, ctx);
             commands.push(Command::Internal(Keyword::Enter(submap_name)));
         } else if child.node_name.to_string() == "@exit" {
             // Special keyword for exiting submap.
             commands.push(Command::Internal(Keyword::Exit));
+        } else if child.node_name.to_string() == "@use" {
+            // Commands of a template.
+            commands.extend(vars::use_template(child, ctx)?);
         }
     }
     Ok(commands)
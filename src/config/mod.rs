@@ -1,80 +1,329 @@
+pub mod cheatsheet;
+mod conflicts;
+pub mod fmt;
+pub mod import;
+mod include;
 mod kdl;
+pub mod model;
 pub mod utils;
+mod vars;
 
 pub use kdl::*;
+use model::ConfigFile;
 
 // Config
-use std::collections::HashMap;
-use std::fs;
+use std::env;
 use std::path::{Path, PathBuf};
 
 // Error
-use crate::error::{MudrasError, WrapError};
-use miette::{Error, Result};
+use crate::error::{LibError, MudrasError, WrapError};
+use miette::{Report, Result};
 use tracing::{error, trace};
 
+/// Environment variable overriding the configuration file path.
+pub const CONFIG_ENV: &str = "MUDRAS_CONFIG";
+
 impl Config {
     /// Get config from crate directory
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
     fn debug_path() -> PathBuf {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         path.push("./config.kdl");
-        return path;
-    }
-    /// Get config from FHS path.
-    fn release_path() -> Result<PathBuf, MudrasError> {
-        let mut path = PathBuf::new();
-        path.push(utils::shellexpand("~/.config/mudras/config.kdl")?);
-        Ok(path)
+        path
     }
-    /// Return configuration from default file path.
-    pub fn get() -> Result<Self, MudrasError> {
+    /// Configuration files looked for, by order of precedence.
+    pub fn search_paths() -> Vec<PathBuf> {
+        let mut paths = vec![];
         #[cfg(debug_assertions)]
-        let path = Self::debug_path();
-
-        #[cfg(not(debug_assertions))]
-        let path = Self::release_path()?;
-        let path = path.display().to_string();
-
-        let config = match Self::from_file(&path) {
-            Ok(v) => v,
-            Err(e) => {
-                let message = format!("Couldn't find a configuration file.",);
-                let help = format!("Create a configuration file at ~/.config/mudras.kdl");
-                let err = WrapError::builder()
-                    .msg(&message)
-                    .help(&help)
-                    .origin(Error::from_err(e))
-                    .build();
-
+        paths.push(Self::debug_path());
+        // XDG_CONFIG_HOME belongs to the current user, not to the one given with --user.
+        if let (false, Ok(dir)) = (utils::has_user(), env::var("XDG_CONFIG_HOME")) {
+            if !dir.is_empty() {
+                paths.push(PathBuf::from(dir).join("mudras/config.kdl"));
+            }
+        }
+        if let Some(home) = utils::home_dir() {
+            paths.push(home.join(".config/mudras/config.kdl"));
+        }
+        paths.push(PathBuf::from("/etc/mudras/config.kdl"));
+        paths
+    }
+    /// Path of the configuration file:
+    /// the given one, the one of MUDRAS_CONFIG, or the first existing search path.
+    pub fn find_path(path: Option<&Path>) -> Result<PathBuf, MudrasError> {
+        let given = path
+            .map(|e| e.to_owned())
+            .or_else(|| env::var(CONFIG_ENV).ok().map(PathBuf::from));
+        if let Some(path) = given {
+            if path.is_file() {
+                return Ok(path);
+            }
+            let message = format!("Couldn't find configuration file {:#?}.", path);
+            let help = format!("Fix the --config argument or the {} variable.", CONFIG_ENV);
+            let err = LibError::builder().msg(&message).help(&help).build();
+            error!("{}", err);
+            return Err(err.into());
+        }
+        let paths = Self::search_paths();
+        match paths.iter().find(|e| e.is_file()) {
+            Some(path) => Ok(path.to_owned()),
+            None => {
+                let message = "Couldn't find a configuration file.";
+                let searched: Vec<String> =
+                    paths.iter().map(|e| format!("  {}", e.display())).collect();
+                let help = format!("Create one of:\n{}", searched.join("\n"));
+                let err = LibError::builder().msg(message).help(&help).build();
                 error!("{}", err);
-                return Err(err.into());
+                Err(err.into())
             }
-        };
-        trace!("Found config file.");
+        }
+    }
+    /// Return configuration from the given file path, or from the default one.
+    pub fn get(path: Option<&Path>) -> Result<Self, MudrasError> {
+        let path = Self::find_path(path)?;
+        let config = Self::from_file(&path.display().to_string())?;
+        trace!("Found config file {:?}.", path);
         trace!("{:#?}", config);
         Ok(config)
     }
+    /// Read a file, the files it includes and the fragments of the config.d directory next to it.
     pub fn from_file(path: &str) -> Result<Self, MudrasError> {
-        let string = fs::read_to_string(path)?;
-        Self::from_kdl(&path, &string)
+        let mut stack = vec![];
+        let mut config = Self::load_file(Path::new(path), &mut stack)?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        let mut fragments = vec![];
+        for extension in ["kdl", "toml", "json"] {
+            let pattern = dir.join("config.d").join(format!("*.{}", extension));
+            fragments.extend(include::glob_files(&pattern.display().to_string())?);
+        }
+        fragments.sort();
+        for fragment in fragments {
+            let other = Self::load_file(&fragment, &mut stack)?;
+            config.merge(other, &fragment.display().to_string());
+        }
+        config.check();
+        Ok(config)
     }
+    /// Parse a configuration string, includes are relative to path.
+    #[cfg_attr(not(test), allow(dead_code))]
     pub fn from_kdl(path: &str, string: &str) -> Result<Self, MudrasError> {
-        let res: Config = knus::parse(path, string)
-            .map_err(miette::Report::new)
-            .unwrap();
-        Ok(res)
+        let config = Self::load(path, string, &mut vec![])?;
+        config.check();
+        Ok(config)
+    }
+    /// Parse a single file, in TOML or JSON depending on its extension, in KDL otherwise.
+    fn parse(path: &str, string: &str) -> Result<Self, MudrasError> {
+        let file = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("toml") => Some(ConfigFile::from_toml(path, string)?),
+            Some("json") => Some(ConfigFile::from_json(path, string)?),
+            _ => None,
+        };
+        if let Some(file) = file {
+            let mut res = file.into_config(path, string)?;
+            let sources = std::mem::take(&mut res.sources);
+            sources.check(path, string, &res.options)?;
+            return Ok(res);
+        }
+        match knus::parse::<Self>(path, string) {
+            Ok(mut res) => {
+                // Spans are only meaningful for this file.
+                let sources = std::mem::take(&mut res.sources);
+                sources.check(path, string, &res.options)?;
+                Ok(res)
+            }
+            Err(e) => {
+                let message = format!("Couldn't parse configuration file {:#?}.", path);
+                let help = "Fix the errors reported above.";
+                let err = WrapError::builder()
+                    .msg(&message)
+                    .help(help)
+                    .origin(Report::new(e))
+                    .build();
+                Err(err.into())
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::input::utils::KeyState;
+    use evdev::{KeyCode, SwitchCode};
     use miette::Result;
+    use pretty_assertions::assert_eq;
 
     #[test]
     fn parse_config_file() -> Result<()> {
-        let config = Config::get()?;
+        let config = Config::get(None)?;
         println!("{:#?}", config);
         Ok(())
     }
+
+    #[test]
+    fn find_config_path() -> Result<()> {
+        let path = Config::debug_path();
+        assert_eq!(path, Config::find_path(Some(&path))?);
+        assert!(Config::find_path(Some(Path::new("/nonexistent/mudras.kdl"))).is_err());
+        assert_eq!(
+            Some(&PathBuf::from("/etc/mudras/config.kdl")),
+            Config::search_paths().last()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_device_scopes() -> Result<()> {
+        let kdl = r#"
+            @device "pad" vendor-product="1234:abcd"
+            @device "yubikey" name="Yubico*" ignore=true
+            Super+Enter {
+              @press {
+                - "kitty"
+              }
+            }
+            Super+Enter device="pad" {
+              @press {
+                - "foot"
+              }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        assert_eq!(Some((0x1234, 0xabcd)), config.devices[0].vendor_product);
+        assert!(config.devices[1].ignore);
+
+        let main = config.submaps.get("main").unwrap();
+        let sequence = vec![
+            (KeyCode::KEY_ENTER, KeyState::Pressed),
+            (KeyCode::KEY_LEFTMETA, KeyState::Pressed),
+        ];
+        let (scope, _, args) = main.find(&sequence, Some("pad")).unwrap();
+        assert_eq!(Some("pad"), scope);
+        assert_eq!(vec![Command::Sh("foot".to_owned())], args.commands);
+        let (scope, _, args) = main.find(&sequence, None).unwrap();
+        assert_eq!(None, scope);
+        assert_eq!(vec![Command::Sh("kitty".to_owned())], args.commands);
+        Ok(())
+    }
+
+    #[test]
+    fn match_modifiers_of_either_side() -> Result<()> {
+        let kdl = r#"
+            Super+Enter {
+              @press {
+                - "kitty"
+              }
+            }
+            Super_R+Enter {
+              @press {
+                - "foot"
+              }
+            }
+            Ctrl_L+q {
+              @press {
+                - "notify-send quit"
+              }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let main = config.submaps.get("main").unwrap();
+        let command = |keys: &[KeyCode]| {
+            let mut sequence: Vec<(KeyCode, KeyState)> =
+                keys.iter().map(|e| (*e, KeyState::Pressed)).collect();
+            sequence.sort_by_key(|a| a.0);
+            main.find(&sequence, None).map(|e| e.2.commands.clone())
+        };
+        let sh = |e: &str| Some(vec![Command::Sh(e.to_owned())]);
+        assert_eq!(
+            sh("kitty"),
+            command(&[KeyCode::KEY_LEFTMETA, KeyCode::KEY_ENTER])
+        );
+        // Side specific binds take precedence.
+        assert_eq!(
+            sh("foot"),
+            command(&[KeyCode::KEY_RIGHTMETA, KeyCode::KEY_ENTER])
+        );
+        assert_eq!(
+            sh("notify-send quit"),
+            command(&[KeyCode::KEY_LEFTCTRL, KeyCode::KEY_Q])
+        );
+        assert_eq!(None, command(&[KeyCode::KEY_RIGHTCTRL, KeyCode::KEY_Q]));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_switches() -> Result<()> {
+        let kdl = r#"
+            @switch "lid" instance="single" {
+              @on {
+                - "swaylock"
+              }
+              @off {
+                - "notify-send welcome back"
+              }
+            }
+            @switch "tablet-mode" {
+              @on {
+                - "squeekboard"
+              }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let lid = config.switches.get(&SwitchCode::SW_LID).unwrap();
+        assert_eq!(Instance::Single, lid.on.as_ref().unwrap().instance);
+        assert_eq!(
+            vec![Command::Sh("notify-send welcome back".to_owned())],
+            lid.off.as_ref().unwrap().commands
+        );
+        let tablet = config.switches.get(&SwitchCode::SW_TABLET_MODE).unwrap();
+        assert_eq!(None, tablet.off);
+        Ok(())
+    }
+
+    #[test]
+    fn expand_bind_braces() -> Result<()> {
+        let kdl = r#"
+            "Super+{1-3}" description="Workspace {1-3}" {
+              @press {
+                - "niri msg action focus-workspace {1-3}"
+                - "notify-send switched"
+              }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let main = config.submaps.get("main").unwrap();
+        assert_eq!(3, main.binds.len());
+        let sequence = vec![
+            (KeyCode::KEY_2, KeyState::Pressed),
+            (KeyCode::KEY_LEFTMETA, KeyState::Pressed),
+        ];
+        let (_, _, args) = main.find(&sequence, None).unwrap();
+        assert_eq!(
+            vec![
+                Command::Sh("niri msg action focus-workspace 2".to_owned()),
+                Command::Sh("notify-send switched".to_owned())
+            ],
+            args.commands
+        );
+        assert_eq!(Some("Workspace 2".to_owned()), args.description);
+
+        let kdl = r#"
+            "Super+{1-3}" {
+              @press {
+                - "niri msg action focus-workspace {1,2}"
+              }
+            }
+        "#;
+        assert!(Config::from_kdl("test.kdl", kdl).is_err());
+        Ok(())
+    }
+    #[test]
+    fn report_unknown_keys() {
+        for name in ["Supr+h", "Super+{h,Foo}"] {
+            let kdl = format!("{} {{\n  @press {{\n    - \"kitty\"\n  }}\n}}", name);
+            assert!(Config::from_kdl("test.kdl", &kdl).is_err(), "{}", name);
+        }
+        let kdl = r#"@options escape="Ctrl+Esc""#;
+        assert!(Config::from_kdl("test.kdl", kdl).is_err());
+    }
 }
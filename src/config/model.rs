@@ -0,0 +1,502 @@
+/*
+* TOML and JSON configuration files.
+*
+* A serde model of the configuration, for files generated by other tools.
+* It is converted to the same configuration as the KDL files,
+* and its JSON Schema is published as config.schema.json (see `mudras schema`).
+*
+* binds = [
+*   { keys = "Super+Enter", commands = ["kitty"] },
+*   { keys = "Super", trigger = "release", enter = "window_manager" },
+* ]
+*
+*/
+
+use super::conflicts::{self, Sources};
+use super::utils;
+use super::{
+    Bind, BindArgs, BindSequence, Command, Config, DeviceFilter, Instance, Keyword, Launcher,
+    Options, SequenceType, Submap, SwitchBind,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+// Error
+use crate::error::{LibError, MudrasError};
+use miette::{Result, SourceSpan};
+
+/// Configuration file in TOML or JSON.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Files to include, relative to this one (paths or glob patterns).
+    #[serde(default)]
+    pub include: Vec<String>,
+    pub options: Option<OptionsFile>,
+    /// Device filters, used as device scopes by submaps and binds.
+    #[serde(default)]
+    pub devices: Vec<DeviceFile>,
+    /// Binds of the main submap.
+    #[serde(default)]
+    pub binds: Vec<BindFile>,
+    #[serde(default)]
+    pub submaps: Vec<SubmapFile>,
+    #[serde(default)]
+    pub switches: Vec<SwitchFile>,
+}
+
+/// Daemon wide options, durations are written like "2s" or "150ms".
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OptionsFile {
+    /// Default to 128.
+    pub max_children: Option<usize>,
+    /// Default to child.
+    pub launcher: Option<Launcher>,
+    /// Default to false.
+    pub grab_pointers: Option<bool>,
+    /// Default to true.
+    pub merge_devices: Option<bool>,
+    /// Default to "Ctrl+Alt+Shift+Escape".
+    pub escape: Option<String>,
+    /// Default to "2s".
+    pub escape_hold: Option<String>,
+    /// Disabled by default, "0s" disables the watchdog too.
+    pub stuck_key_timeout: Option<String>,
+    pub pause_timeout: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeviceFile {
+    /// Filter name.
+    pub name: String,
+    /// Device name pattern.
+    pub device_name: Option<String>,
+    /// Vendor and product ids (ex: "1234:abcd").
+    pub vendor_product: Option<String>,
+    /// Physical path pattern.
+    pub phys: Option<String>,
+    /// Udev properties the device must have.
+    #[serde(default)]
+    pub udev: BTreeMap<String, String>,
+    #[serde(default)]
+    pub ignore: bool,
+    pub grab: Option<bool>,
+    pub seat: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SubmapFile {
+    pub name: String,
+    /// Default device scope of the submap binds.
+    pub device: Option<String>,
+    #[serde(default)]
+    pub pause: bool,
+    #[serde(default)]
+    pub binds: Vec<BindFile>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trigger {
+    #[default]
+    Press,
+    Release,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BindFile {
+    /// Keys, brace groups accepted (ex: "Super+{1-9}").
+    pub keys: String,
+    #[serde(default)]
+    pub trigger: Trigger,
+    #[serde(default)]
+    pub repeat: bool,
+    /// Default to true.
+    pub swallow: Option<bool>,
+    pub instance: Option<Instance>,
+    pub cooldown: Option<String>,
+    pub max_rate: Option<u32>,
+    pub device: Option<String>,
+    pub description: Option<String>,
+    /// Shell commands.
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// Submap to enter, after the commands.
+    pub enter: Option<String>,
+    /// Exit the submap, after the commands.
+    #[serde(default)]
+    pub exit: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SwitchFile {
+    /// Switch name (ex: "lid", "tablet-mode").
+    pub name: String,
+    pub instance: Option<Instance>,
+    pub cooldown: Option<String>,
+    pub max_rate: Option<u32>,
+    /// Shell commands run when the switch turns on.
+    pub on: Option<Vec<String>>,
+    /// Shell commands run when the switch turns off.
+    pub off: Option<Vec<String>>,
+}
+
+impl ConfigFile {
+    pub fn from_toml(path: &str, string: &str) -> Result<Self, MudrasError> {
+        toml::from_str(string).map_err(|e| parse_error(path, &e.to_string()))
+    }
+    pub fn from_json(path: &str, string: &str) -> Result<Self, MudrasError> {
+        serde_json::from_str(string).map_err(|e| parse_error(path, &e.to_string()))
+    }
+
+    /// JSON Schema of the configuration files.
+    pub fn schema() -> String {
+        let schema = schemars::schema_for!(ConfigFile);
+        serde_json::to_string_pretty(&schema).unwrap() + "\n"
+    }
+
+    /// Validate and convert to a configuration,
+    /// with where binds are defined in the file string for conflict checks.
+    pub fn into_config(self, path: &str, string: &str) -> Result<Config, MudrasError> {
+        let invalid = |what: String, e: String| {
+            let message = format!("Invalid {} in {:#?}: {}.", what, path, e);
+            let help = "Fix the file, its schema is printed by `mudras schema`.";
+            MudrasError::from(LibError::builder().msg(&message).help(help).build())
+        };
+        let mut submaps = HashMap::new();
+        let mut sources = Sources::default();
+        for submap in &self.submaps {
+            let span = locate(string, &submap.name, |_| false);
+            sources.submaps.insert(submap.name.clone(), span);
+        }
+        let main = SubmapFile {
+            name: "main".to_owned(),
+            binds: self.binds,
+            ..Default::default()
+        };
+        for submap in [main].into_iter().chain(self.submaps) {
+            let name = submap.name.clone();
+            let submap = submap
+                .into_submap(string, &mut sources)
+                .map_err(|e| invalid(format!("submap {:?}", name), e))?;
+            submaps.insert(name, submap);
+        }
+        let mut switches = HashMap::new();
+        for switch in self.switches {
+            let name = switch.name.clone();
+            let switch = switch
+                .into_switch()
+                .map_err(|e| invalid(format!("switch {:?}", name), e))?;
+            switches.insert(switch.switch, switch);
+        }
+        let mut devices = vec![];
+        for device in self.devices {
+            let name = device.name.clone();
+            devices.push(
+                device
+                    .into_filter()
+                    .map_err(|e| invalid(format!("device {:?}", name), e))?,
+            );
+        }
+        let has_options = self.options.is_some();
+        if has_options {
+            sources.options = string
+                .find("options")
+                .map(|offset| (offset, "options".len()).into());
+        }
+        let options = self
+            .options
+            .unwrap_or_default()
+            .into_options()
+            .map_err(|e| invalid("options".to_owned(), e))?;
+        Ok(Config {
+            submaps,
+            options,
+            devices,
+            switches,
+            includes: self.include,
+            has_options,
+            sources,
+        })
+    }
+}
+
+fn parse_error(path: &str, e: &str) -> MudrasError {
+    let message = format!(
+        "Couldn't parse configuration file {:#?}: {}",
+        path,
+        e.trim()
+    );
+    let help = "Fix the file, its schema is printed by `mudras schema`.";
+    LibError::builder().msg(&message).help(help).build().into()
+}
+
+/// Span of a quoted string of the file, the first one not taken yet.
+fn locate(string: &str, text: &str, taken: impl Fn(usize) -> bool) -> SourceSpan {
+    string
+        .match_indices(text)
+        .map(|(offset, _)| offset)
+        .find(|offset| {
+            let before = string[..*offset].chars().last();
+            let after = string[offset + text.len()..].chars().next();
+            before == after && matches!(before, Some('"' | '\'')) && !taken(*offset)
+        })
+        .map(|offset| (offset, text.len()).into())
+        .unwrap_or((0, 0).into())
+}
+
+fn duration(string: &str) -> Result<Duration, String> {
+    humantime::parse_duration(string).map_err(|e| format!("{:?}: {}", string, e))
+}
+
+impl OptionsFile {
+    fn into_options(self) -> Result<Options, String> {
+        let mut options = Options::default();
+        if let Some(e) = self.max_children {
+            options.max_children = e;
+        }
+        if let Some(e) = self.launcher {
+            options.launcher = e;
+        }
+        if let Some(e) = self.grab_pointers {
+            options.grab_pointers = e;
+        }
+        if let Some(e) = self.merge_devices {
+            options.merge_devices = e;
+        }
+        if let Some(chord) = self.escape {
+            options.escape = keys(&chord, &SequenceType::Press)?
+                .into_iter()
+                .map(|e| e.0)
+                .collect();
+        }
+        if let Some(e) = self.escape_hold {
+            options.escape_hold = duration(&e)?;
+        }
+        if let Some(e) = self.stuck_key_timeout {
+            let timeout = duration(&e)?;
+            options.stuck_key_timeout = (!timeout.is_zero()).then_some(timeout);
+        }
+        if let Some(e) = self.pause_timeout {
+            options.pause_timeout = Some(duration(&e)?);
+        }
+        Ok(options)
+    }
+}
+
+/// Keys of a chord, unknown names are errors.
+fn keys(chord: &str, sequence_type: &SequenceType) -> Result<BindSequence, String> {
+    utils::bind_to_keys(chord, sequence_type).map_err(|e| e.to_string())
+}
+
+impl DeviceFile {
+    fn into_filter(self) -> Result<DeviceFilter, String> {
+        for pattern in self.device_name.iter().chain(&self.phys) {
+            glob::Pattern::new(pattern).map_err(|e| format!("{:?}: {}", pattern, e))?;
+        }
+        let vendor_product = match &self.vendor_product {
+            Some(e) => Some(utils::parse_vendor_product(e).map_err(|e| e.to_string())?),
+            None => None,
+        };
+        Ok(DeviceFilter {
+            name: self.name,
+            device_name: self.device_name,
+            vendor_product,
+            phys: self.phys,
+            udev: self.udev.into_iter().collect(),
+            ignore: self.ignore,
+            grab: self.grab,
+            seat: self.seat,
+        })
+    }
+}
+
+impl SubmapFile {
+    fn into_submap(self, string: &str, sources: &mut Sources) -> Result<Submap, String> {
+        let mut binds = vec![];
+        for bind in self.binds {
+            let keys = bind.keys.clone();
+            let expanded = bind
+                .into_binds()
+                .map_err(|e| format!("bind {:?}: {}", keys, e))?;
+            // Binds with the same keys are told apart by their order in the file.
+            let taken = |offset| sources.binds.iter().any(|e| e.span.offset() == offset);
+            let span = locate(string, &keys, taken);
+            let device = self.device.as_deref();
+            let definitions = conflicts::definitions(&self.name, device, &expanded, span);
+            sources.binds.extend(definitions);
+            binds.extend(expanded);
+        }
+        let mut submap = Submap {
+            name: self.name,
+            device: self.device,
+            pause: self.pause,
+            ..Default::default()
+        };
+        submap.insert_binds(binds);
+        Ok(submap)
+    }
+}
+
+impl BindFile {
+    fn into_binds(self) -> Result<Vec<Bind>, String> {
+        let sequence_type = match self.trigger {
+            Trigger::Press => SequenceType::Press,
+            Trigger::Release => SequenceType::Release,
+        };
+        let mut commands: Vec<Command> = self.commands.into_iter().map(Command::Sh).collect();
+        if let Some(submap) = self.enter {
+            commands.push(Command::Internal(Keyword::Enter(submap)));
+        }
+        if self.exit {
+            commands.push(Command::Internal(Keyword::Exit));
+        }
+        let args = BindArgs {
+            commands,
+            swallow: self.swallow.unwrap_or(true),
+            repeat: self.repeat,
+            exact: true,
+            instance: self.instance.unwrap_or_default(),
+            cooldown: self.cooldown.as_deref().map(duration).transpose()?,
+            max_rate: self.max_rate,
+            device: self.device,
+            description: self.description,
+        };
+        let names = utils::expand_braces(&self.keys);
+        let bind = Bind {
+            sequence: keys(&names[0], &sequence_type)?,
+            args,
+        };
+        bind.expand(&self.keys)
+    }
+}
+
+impl SwitchFile {
+    fn into_switch(self) -> Result<SwitchBind, String> {
+        let switch = utils::switch_from_name(&self.name).map_err(|e| e.to_string())?;
+        let args = BindArgs {
+            instance: self.instance.unwrap_or_default(),
+            cooldown: self.cooldown.as_deref().map(duration).transpose()?,
+            max_rate: self.max_rate,
+            ..Default::default()
+        };
+        let args = |commands: Vec<String>| BindArgs {
+            commands: commands.into_iter().map(Command::Sh).collect(),
+            ..args.clone()
+        };
+        Ok(SwitchBind {
+            name: self.name,
+            switch,
+            on: self.on.map(args),
+            off: self.off.map(args),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::modifier;
+    use crate::input::utils::KeyState;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn convert_toml_and_json() -> Result<()> {
+        let toml = r#"
+            [options]
+            launcher = "systemd"
+
+            [[binds]]
+            keys = "Super+{1-3}"
+            commands = ["niri msg action focus-workspace {1-3}"]
+
+            [[binds]]
+            keys = "Super"
+            trigger = "release"
+            enter = "nav"
+
+            [[submaps]]
+            name = "nav"
+            binds = [{ keys = "Escape", exit = true }]
+
+            [[switches]]
+            name = "lid"
+            on = ["swaylock"]
+        "#;
+        let config = Config::parse("config.toml", toml)?;
+        assert_eq!(Launcher::Systemd, config.options.launcher);
+        let main = &config.submaps["main"];
+        assert_eq!(4, main.binds.len());
+        let sequence = vec![(modifier::SUPER, KeyState::Released)];
+        assert_eq!(
+            vec![Command::Internal(Keyword::Enter("nav".to_owned()))],
+            main.binds[&sequence].commands
+        );
+        assert!(config.switches.contains_key(&evdev::SwitchCode::SW_LID));
+
+        let json = r#"{ "binds": [{ "keys": "Super+Enter", "commands": ["kitty"] }] }"#;
+        let config = Config::parse("config.json", json)?;
+        assert_eq!(1, config.submaps["main"].binds.len());
+
+        // Unknown fields and keys are errors.
+        assert!(Config::parse("config.json", r#"{ "bind": [] }"#).is_err());
+        let json = r#"{ "binds": [{ "keys": "Super+Hyperdrive" }] }"#;
+        assert!(Config::parse("config.json", json).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn detect_conflicts_in_toml_and_json() -> Result<()> {
+        let toml = r#"
+            [[binds]]
+            keys = "Super+Enter"
+            commands = ["kitty"]
+
+            [[binds]]
+            keys = "Super+Enter"
+            commands = ["foot"]
+        "#;
+        let file = ConfigFile::from_toml("config.toml", toml)?;
+        let config = file.into_config("config.toml", toml)?;
+        let conflicts = config.sources.conflicts(&config.options);
+        assert_eq!(
+            vec![r#"Super+Enter is bound twice in submap "main"."#.to_owned()],
+            conflicts
+                .iter()
+                .map(|e| e.message.clone())
+                .collect::<Vec<_>>()
+        );
+        // Both binds are labeled, in order.
+        let offsets: Vec<usize> = conflicts[0].labels.iter().map(|e| e.0.offset()).collect();
+        let first = toml.find("Super+Enter").unwrap();
+        let second = toml.rfind("Super+Enter").unwrap();
+        assert_eq!(vec![first, second], offsets);
+        assert!(Config::parse("config.toml", toml).is_err());
+
+        let json = r#"{
+            "binds": [
+                { "keys": "Super+Enter", "commands": ["kitty"] },
+                { "keys": "Super+Enter", "commands": ["foot"] }
+            ]
+        }"#;
+        assert!(Config::parse("config.json", json).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn published_schema_is_up_to_date() {
+        let published = include_str!("../../config.schema.json");
+        assert!(
+            published == ConfigFile::schema(),
+            "run `cargo run -- schema > config.schema.json`"
+        );
+    }
+}
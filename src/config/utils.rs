@@ -1,7 +1,12 @@
-use super::{Bind, Binds, SequenceType};
+use super::SequenceType;
+use crate::input::utils::key_name;
+use crate::input::virtuals::get_all_switches;
+use crate::input::{modifier, pointer};
 
-use evdev::KeyCode;
-use std::path::Path;
+use evdev::{KeyCode, SwitchCode};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 // Error
 use crate::{
@@ -9,26 +14,106 @@ use crate::{
     input::utils::KeyState,
 };
 use miette::Result;
-use tracing::{error, trace};
+use tracing::error;
 
-pub fn get_modifiers(binds: &Binds) -> Result<Vec<KeyCode>, MudrasError> {
-    let mut mods: Vec<KeyCode> = vec![];
-    for (sequence, args) in binds.clone() {
-        if let Some(key) = sequence.first() {
-            mods.push(key.0);
+/// Parse "vendor:product" hexadecimal ids (ex: "046d:c52b").
+pub fn parse_vendor_product(ids: &str) -> Result<(u16, u16), MudrasError> {
+    let parsed = ids.split_once(':').and_then(|(vendor, product)| {
+        let vendor = u16::from_str_radix(vendor, 16).ok()?;
+        let product = u16::from_str_radix(product, 16).ok()?;
+        Some((vendor, product))
+    });
+    match parsed {
+        Some(v) => Ok(v),
+        None => {
+            let message = format!("Couldn't parse vendor and product ids {:#?}.", ids);
+            let err = LibError::builder()
+                .msg(&message)
+                .help("Use hexadecimal ids separated by a colon (ex: \"046d:c52b\").")
+                .build();
+            Err(err.into())
         }
     }
-    mods.sort();
-    mods.dedup();
-    Ok(mods)
+}
+
+/// Find a switch by its name (ex: "lid", "tablet-mode", "SW_HEADPHONE_INSERT").
+pub fn switch_from_name(name: &str) -> Result<SwitchCode, MudrasError> {
+    let name = name.to_lowercase().replace('-', "_");
+    let name = name.trim_start_matches("sw_");
+    let switch = get_all_switches()
+        .iter()
+        .find(|e| format!("{:?}", e).to_lowercase().trim_start_matches("sw_") == name);
+    match switch {
+        Some(v) => Ok(*v),
+        None => {
+            let message = format!("Unknown switch {:#?}.", name);
+            let err = LibError::builder()
+                .msg(&message)
+                .help("Use a switch name like \"lid\", \"tablet-mode\" or \"headphone-insert\".")
+                .build();
+            Err(err.into())
+        }
+    }
+}
+
+/// Home directory of the user given with `--user`.
+static USER_HOME: OnceLock<PathBuf> = OnceLock::new();
+
+/// Resolve "~" against the home directory of a user rather than the current one's.
+pub fn set_user(name: &str) -> Result<(), MudrasError> {
+    let home = user_home(name)?;
+    let _ = USER_HOME.set(home);
+    Ok(())
+}
+
+/// Whether "~" is resolved against the home directory of another user.
+pub fn has_user() -> bool {
+    USER_HOME.get().is_some()
+}
+
+/// Home directory of the configuration owner.
+pub fn home_dir() -> Option<PathBuf> {
+    USER_HOME.get().cloned().or_else(dirs::home_dir)
+}
+
+/// Home directory of a user, from /etc/passwd.
+pub fn user_home(name: &str) -> Result<PathBuf, MudrasError> {
+    match passwd_home(&fs::read_to_string("/etc/passwd")?, name) {
+        Some(v) => Ok(v),
+        None => {
+            let message = format!("Couldn't find the home directory of user {:#?}.", name);
+            let err = LibError::builder()
+                .msg(&message)
+                .help("Give the name of an existing user (see /etc/passwd).")
+                .build();
+            Err(err.into())
+        }
+    }
+}
+
+/// Home directory field of a user line ("name:x:uid:gid:gecos:home:shell").
+fn passwd_home(passwd: &str, name: &str) -> Option<PathBuf> {
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        match fields[..] {
+            [user, _, _, _, _, home, ..] if user == name => Some(PathBuf::from(home)),
+            _ => None,
+        }
+    })
+}
+
+/// Replace a leading tild "~" with the home directory.
+pub fn expand_tilde(relpath: &str) -> String {
+    match (relpath.starts_with("~"), home_dir()) {
+        (true, Some(home)) => relpath.replacen("~", home.to_str().unwrap(), 1),
+        _ => relpath.to_owned(),
+    }
 }
 
 /// Expand tild "~" in file path.
+/// Fails if the file doesn't exist.
 pub fn shellexpand(relpath: &str) -> Result<String, MudrasError> {
-    let source: String = match relpath.starts_with("~") {
-        false => relpath.to_owned(),
-        true => relpath.replace("~", dirs::home_dir().unwrap().to_str().unwrap()),
-    };
+    let source = expand_tilde(relpath);
 
     let path = Path::new(&source);
     if path.exists() {
@@ -40,11 +125,131 @@ pub fn shellexpand(relpath: &str) -> Result<String, MudrasError> {
             .msg(&message)
             .help("Are you sure the file exist?")
             .build();
-        return Err(err.into());
+        Err(err.into())
+    }
+}
+
+/// Key names of the configuration (case insensitive).
+/// The first name of a key is the one it is displayed with.
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Escape", KeyCode::KEY_ESC),
+    ("Super_L", KeyCode::KEY_LEFTMETA),
+    ("Super", modifier::SUPER),
+    ("Hyper", modifier::SUPER),
+    ("Super_R", KeyCode::KEY_RIGHTMETA),
+    ("Ctrl_L", KeyCode::KEY_LEFTCTRL),
+    ("Ctrl", modifier::CTRL),
+    ("Ctrl_R", KeyCode::KEY_RIGHTCTRL),
+    ("Shift_L", KeyCode::KEY_LEFTSHIFT),
+    ("Shift", modifier::SHIFT),
+    ("Shift_R", KeyCode::KEY_RIGHTSHIFT),
+    ("Alt_L", KeyCode::KEY_LEFTALT),
+    ("Alt", modifier::ALT),
+    ("Alt_R", KeyCode::KEY_RIGHTALT),
+    ("AltGr", KeyCode::KEY_RIGHTALT),
+    ("Tab", KeyCode::KEY_TAB),
+    ("Backspace", KeyCode::KEY_BACKSPACE),
+    ("Enter", KeyCode::KEY_ENTER),
+    ("Space", KeyCode::KEY_SPACE),
+    ("Left", KeyCode::KEY_LEFT),
+    ("Down", KeyCode::KEY_DOWN),
+    ("Up", KeyCode::KEY_UP),
+    ("Right", KeyCode::KEY_RIGHT),
+    ("1", KeyCode::KEY_1),
+    ("2", KeyCode::KEY_2),
+    ("3", KeyCode::KEY_3),
+    ("4", KeyCode::KEY_4),
+    ("5", KeyCode::KEY_5),
+    ("6", KeyCode::KEY_6),
+    ("7", KeyCode::KEY_7),
+    ("8", KeyCode::KEY_8),
+    ("9", KeyCode::KEY_9),
+    ("0", KeyCode::KEY_0),
+    ("q", KeyCode::KEY_Q),
+    ("w", KeyCode::KEY_W),
+    ("e", KeyCode::KEY_E),
+    ("r", KeyCode::KEY_R),
+    ("t", KeyCode::KEY_T),
+    ("y", KeyCode::KEY_Y),
+    ("u", KeyCode::KEY_U),
+    ("i", KeyCode::KEY_I),
+    ("o", KeyCode::KEY_O),
+    ("p", KeyCode::KEY_P),
+    ("a", KeyCode::KEY_A),
+    ("s", KeyCode::KEY_S),
+    ("d", KeyCode::KEY_D),
+    ("f", KeyCode::KEY_F),
+    ("g", KeyCode::KEY_G),
+    ("h", KeyCode::KEY_H),
+    ("j", KeyCode::KEY_J),
+    ("k", KeyCode::KEY_K),
+    ("l", KeyCode::KEY_L),
+    ("z", KeyCode::KEY_Z),
+    ("x", KeyCode::KEY_X),
+    ("c", KeyCode::KEY_C),
+    ("v", KeyCode::KEY_V),
+    ("b", KeyCode::KEY_B),
+    ("n", KeyCode::KEY_N),
+    ("m", KeyCode::KEY_M),
+    ("btn_left", KeyCode::BTN_LEFT),
+    ("btn_right", KeyCode::BTN_RIGHT),
+    ("btn_middle", KeyCode::BTN_MIDDLE),
+    ("btn_side", KeyCode::BTN_SIDE),
+    ("btn_extra", KeyCode::BTN_EXTRA),
+    ("btn_forward", KeyCode::BTN_FORWARD),
+    ("btn_back", KeyCode::BTN_BACK),
+    ("WheelUp", pointer::WHEEL_UP),
+    ("WheelDown", pointer::WHEEL_DOWN),
+    ("WheelLeft", pointer::WHEEL_LEFT),
+    ("WheelRight", pointer::WHEEL_RIGHT),
+    ("brightness_up", KeyCode::KEY_BRIGHTNESSUP),
+    ("brightness_down", KeyCode::KEY_BRIGHTNESSDOWN),
+];
+
+/// Keys displayed first in chords.
+pub const MODIFIERS: &[KeyCode] = &[
+    modifier::CTRL,
+    KeyCode::KEY_LEFTCTRL,
+    KeyCode::KEY_RIGHTCTRL,
+    modifier::SHIFT,
+    KeyCode::KEY_LEFTSHIFT,
+    KeyCode::KEY_RIGHTSHIFT,
+    modifier::ALT,
+    KeyCode::KEY_LEFTALT,
+    KeyCode::KEY_RIGHTALT,
+    modifier::SUPER,
+    KeyCode::KEY_LEFTMETA,
+    KeyCode::KEY_RIGHTMETA,
+];
+
+/// Find a key by its configuration name,
+/// or by its evdev name (ex: "KEY_F1", "BTN_TASK").
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key)
+        .or_else(|| name.to_uppercase().parse().ok())
+}
+
+/// Documented spelling of a key name (ex: "super" gives "Super", "key_f1" gives "KEY_F1").
+pub fn canonical_key_name(name: &str) -> Option<String> {
+    if let Some((e, _)) = KEY_NAMES.iter().find(|(e, _)| e.eq_ignore_ascii_case(name)) {
+        return Some(e.to_string());
+    }
+    let name = name.to_uppercase();
+    name.parse::<KeyCode>().ok().map(|_| name)
+}
+
+/// Configuration name of a key, the evdev name if it has none.
+pub fn name_from_key(key: KeyCode) -> String {
+    match KEY_NAMES.iter().find(|(_, e)| *e == key) {
+        Some((name, _)) => name.to_string(),
+        None => key_name(key),
     }
 }
 
-/// Transform a bind definition into its keycode.
+/// Transform a bind definition into its keycode, unknown key names are errors.
 pub fn bind_to_keys(
     bind: &str,
     sequence_type: &SequenceType,
@@ -53,92 +258,104 @@ pub fn bind_to_keys(
     let mut sequence = vec![];
 
     for key in keys {
-        let keycode = match &*key.to_lowercase() {
-            "escape" => evdev::KeyCode::KEY_ESC,
-
-            "super" => KeyCode::KEY_LEFTMETA,
-            "super_l" => KeyCode::KEY_LEFTMETA,
-            "super_r" => KeyCode::KEY_RIGHTMETA,
-
-            "ctrl" => evdev::KeyCode::KEY_LEFTCTRL,
-            "ctrl_l" => evdev::KeyCode::KEY_LEFTCTRL,
-            "ctrl_r" => evdev::KeyCode::KEY_RIGHTCTRL,
-
-            "shift" => evdev::KeyCode::KEY_LEFTSHIFT,
-            "shift_l" => evdev::KeyCode::KEY_LEFTSHIFT,
-            "shift_r" => evdev::KeyCode::KEY_RIGHTSHIFT,
-
-            "alt" => evdev::KeyCode::KEY_LEFTALT,
-            "tab" => evdev::KeyCode::KEY_TAB,
-            "backspace" => evdev::KeyCode::KEY_BACKSPACE,
-
-            "enter" => evdev::KeyCode::KEY_ENTER,
-            "space" => evdev::KeyCode::KEY_SPACE,
-
-            "left" => evdev::KeyCode::KEY_LEFT,
-            "down" => evdev::KeyCode::KEY_DOWN,
-            "up" => evdev::KeyCode::KEY_UP,
-            "right" => evdev::KeyCode::KEY_RIGHT,
-
-            "1" => evdev::KeyCode::KEY_1,
-            "2" => evdev::KeyCode::KEY_2,
-            "3" => evdev::KeyCode::KEY_3,
-            "4" => evdev::KeyCode::KEY_4,
-            "5" => evdev::KeyCode::KEY_5,
-            "6" => evdev::KeyCode::KEY_6,
-            "7" => evdev::KeyCode::KEY_7,
-            "8" => evdev::KeyCode::KEY_8,
-            "9" => evdev::KeyCode::KEY_9,
-            "0" => evdev::KeyCode::KEY_0,
-
-            "q" => evdev::KeyCode::KEY_Q,
-            "w" => evdev::KeyCode::KEY_W,
-            "e" => evdev::KeyCode::KEY_E,
-            "r" => evdev::KeyCode::KEY_R,
-            "t" => evdev::KeyCode::KEY_T,
-            "y" => evdev::KeyCode::KEY_Y,
-            "u" => evdev::KeyCode::KEY_U,
-            "i" => evdev::KeyCode::KEY_I,
-            "o" => evdev::KeyCode::KEY_O,
-            "p" => evdev::KeyCode::KEY_P,
-
-            "a" => evdev::KeyCode::KEY_A,
-            "s" => evdev::KeyCode::KEY_S,
-            "d" => evdev::KeyCode::KEY_D,
-            "f" => evdev::KeyCode::KEY_F,
-            "g" => evdev::KeyCode::KEY_G,
-            "h" => evdev::KeyCode::KEY_H,
-            "j" => evdev::KeyCode::KEY_J,
-            "k" => evdev::KeyCode::KEY_K,
-            "l" => evdev::KeyCode::KEY_L,
-
-            "z" => evdev::KeyCode::KEY_Z,
-            "x" => evdev::KeyCode::KEY_X,
-            "c" => evdev::KeyCode::KEY_C,
-            "v" => evdev::KeyCode::KEY_V,
-            "b" => evdev::KeyCode::KEY_B,
-            "n" => evdev::KeyCode::KEY_N,
-            "m" => evdev::KeyCode::KEY_M,
-
-            "brightness_up" => evdev::KeyCode::KEY_BRIGHTNESSUP,
-            "brightness_down" => evdev::KeyCode::KEY_BRIGHTNESSDOWN,
-
-            _ => evdev::KeyCode::KEY_RESERVED,
+        let Some(keycode) = key_from_name(key) else {
+            let message = format!("Unknown key {:?} in {:?}.", key, bind);
+            let help = "Use a key name of the README, or an evdev name (ex: KEY_F1).";
+            let err = LibError::builder().msg(&message).help(help).build();
+            return Err(err.into());
         };
         sequence.push((keycode, KeyState::Pressed));
     }
 
-    match &sequence_type {
-        SequenceType::Release => {
-            if let Some((_key, ref mut state)) = sequence.iter_mut().last() {
-                *state = KeyState::Released;
-            }
+    if sequence_type == &SequenceType::Release {
+        if let Some((_key, ref mut state)) = sequence.iter_mut().last() {
+            *state = KeyState::Released;
         }
-        _ => {}
-    };
+    }
     Ok(sequence)
 }
 
+/// Expand brace groups, sxhkd style, in order
+/// (ex: "Super+{_,Shift+}{1-3}" gives "Super+1", ..., "Super+Shift+3").
+/// A group holds comma separated alternatives and/or ranges ("1-9", "a-z", "F1-F12"),
+/// "_" is an empty alternative.
+/// Braces of variables ("${name}") and escaped braces ("\{") are kept.
+pub fn expand_braces(string: &str) -> Vec<String> {
+    let chars: Vec<char> = string.chars().collect();
+    let mut res = vec![String::new()];
+    let mut i = 0;
+    while i < chars.len() {
+        let group = match chars[i] {
+            '{' if i == 0 || chars[i - 1] != '$' => chars[i + 1..]
+                .iter()
+                .position(|c| *c == '}')
+                .and_then(|end| {
+                    let inner: String = chars[i + 1..i + 1 + end].iter().collect();
+                    Some((alternatives(&inner)?, end))
+                }),
+            _ => None,
+        };
+        match group {
+            Some((alternatives, end)) => {
+                res = res
+                    .iter()
+                    .flat_map(|prefix| alternatives.iter().map(move |e| format!("{prefix}{e}")))
+                    .collect();
+                i += end + 2;
+            }
+            None => {
+                let mut c = chars[i];
+                if c == '\\' && matches!(chars.get(i + 1), Some('{' | '}')) {
+                    i += 1;
+                    c = chars[i];
+                }
+                res.iter_mut().for_each(|e| e.push(c));
+                i += 1;
+            }
+        }
+    }
+    res
+}
+
+/// Alternatives of a brace group, if it is one.
+fn alternatives(inner: &str) -> Option<Vec<String>> {
+    let items: Vec<&str> = inner.split(',').collect();
+    let mut is_group = items.len() > 1;
+    let mut res = vec![];
+    for item in items {
+        match range(item) {
+            Some(e) => {
+                is_group = true;
+                res.extend(e);
+            }
+            None if item == "_" => res.push(String::new()),
+            None => res.push(item.to_owned()),
+        }
+    }
+    is_group.then_some(res)
+}
+
+/// Items of a numeric ("1-9", "F1-F12") or character ("a-z") range.
+fn range(item: &str) -> Option<Vec<String>> {
+    let (start, end) = item.split_once('-')?;
+    fn split(e: &str) -> Option<(&str, u32)> {
+        let (prefix, number) = e.split_at(e.find(|c: char| c.is_ascii_digit())?);
+        Some((prefix, number.parse::<u32>().ok()?))
+    }
+    if let (Some((prefix, start)), Some((end_prefix, end))) = (split(start), split(end)) {
+        return (prefix == end_prefix && start <= end)
+            .then(|| (start..=end).map(|e| format!("{prefix}{e}")).collect());
+    }
+    let mut start = start.chars();
+    let mut end = end.chars();
+    match (start.next(), start.next(), end.next(), end.next()) {
+        (Some(a), None, Some(b), None) if a.is_ascii_alphabetic() && a <= b => {
+            Some((a..=b).map(String::from).collect())
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,21 +364,104 @@ mod tests {
 
     #[test]
     fn parse_sequence_to_keys() -> Result<()> {
-        let res = bind_to_keys("", &SequenceType::Press)?;
-        let empty: Vec<(KeyCode, KeyState)> = vec![(KeyCode::KEY_RESERVED, KeyState::Pressed)];
-        assert_eq!(empty, res);
+        assert!(bind_to_keys("", &SequenceType::Press).is_err());
+        assert!(bind_to_keys("Supr+h", &SequenceType::Press).is_err());
+        let res = bind_to_keys("Hyper+h", &SequenceType::Press)?;
+        assert_eq!(modifier::SUPER, res[0].0);
 
         let res = bind_to_keys("Super", &SequenceType::Release)?;
-        assert_eq!(vec![(KeyCode::KEY_LEFTMETA, KeyState::Released)], res);
+        assert_eq!(vec![(modifier::SUPER, KeyState::Released)], res);
 
         let res = bind_to_keys("Super+T", &SequenceType::Press)?;
         assert_eq!(
             vec![
-                (KeyCode::KEY_LEFTMETA, KeyState::Pressed),
+                (modifier::SUPER, KeyState::Pressed),
                 (KeyCode::KEY_T, KeyState::Pressed)
             ],
             res
         );
+
+        let res = bind_to_keys("Super+BTN_SIDE", &SequenceType::Press)?;
+        assert_eq!(
+            vec![
+                (modifier::SUPER, KeyState::Pressed),
+                (KeyCode::BTN_SIDE, KeyState::Pressed)
+            ],
+            res
+        );
+
+        let res = bind_to_keys("Super+WheelUp", &SequenceType::Press)?;
+        assert_eq!(
+            vec![
+                (modifier::SUPER, KeyState::Pressed),
+                (pointer::WHEEL_UP, KeyState::Pressed)
+            ],
+            res
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn convert_key_names() -> Result<()> {
+        assert_eq!(Some(modifier::SUPER), key_from_name("super"));
+        assert_eq!(Some(KeyCode::KEY_LEFTMETA), key_from_name("Super_L"));
+        assert_eq!(Some(KeyCode::KEY_RIGHTALT), key_from_name("AltGr"));
+        assert_eq!(Some(KeyCode::KEY_F1), key_from_name("KEY_F1"));
+        assert_eq!(None, key_from_name("hyperdrive"));
+        assert_eq!("Super_L", name_from_key(KeyCode::KEY_LEFTMETA));
+        assert_eq!("Super", name_from_key(modifier::SUPER));
+        assert_eq!("brightness_up", name_from_key(KeyCode::KEY_BRIGHTNESSUP));
+        assert_eq!("KEY_F1", name_from_key(KeyCode::KEY_F1));
+        let res = bind_to_keys("Super+KEY_F1", &SequenceType::Press)?;
+        assert_eq!(
+            vec![
+                (modifier::SUPER, KeyState::Pressed),
+                (KeyCode::KEY_F1, KeyState::Pressed)
+            ],
+            res
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn expand_brace_groups() {
+        assert_eq!(
+            vec!["Super+1", "Super+2", "Super+3"],
+            expand_braces("Super+{1-3}")
+        );
+        assert_eq!(
+            vec!["Super+h", "Super+l", "Super+Shift+h", "Super+Shift+l"],
+            expand_braces("Super+{_,Shift+}{h,l}")
+        );
+        assert_eq!(vec!["F11", "F12"], expand_braces("{F11-F12}"));
+        assert_eq!(vec!["a", "b", "c", "0"], expand_braces("{a-c,0}"));
+        // Not groups
+        assert_eq!(
+            vec!["awk '{print $1}' ${HOME}"],
+            expand_braces("awk '{print $1}' ${HOME}")
+        );
+        assert_eq!(vec!["{a,b}"], expand_braces("\\{a,b\\}"));
+    }
+
+    #[test]
+    fn find_user_home() {
+        let passwd = "root:x:0:0::/root:/bin/bash\nanon:x:1000:100:Anon:/home/anon:/bin/fish\n";
+        assert_eq!(
+            Some(PathBuf::from("/home/anon")),
+            passwd_home(passwd, "anon")
+        );
+        assert_eq!(None, passwd_home(passwd, "nobody"));
+    }
+
+    #[test]
+    fn parse_switch_names() -> Result<()> {
+        assert_eq!(SwitchCode::SW_LID, switch_from_name("lid")?);
+        assert_eq!(SwitchCode::SW_TABLET_MODE, switch_from_name("tablet-mode")?);
+        assert_eq!(
+            SwitchCode::SW_HEADPHONE_INSERT,
+            switch_from_name("SW_HEADPHONE_INSERT")?
+        );
+        assert!(switch_from_name("trapdoor").is_err());
         Ok(())
     }
 }
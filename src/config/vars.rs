@@ -0,0 +1,313 @@
+/*
+* Variables and command templates.
+*
+* Expanded at parse time, and local to the file that defines them.
+*
+* @vars {
+*   niri "niri msg action"
+* }
+* @template "niri-action" {
+*   - "${niri} ${1}"
+* }
+* Super+h {
+*   @press {
+*     - "${niri} focus-column-left"
+*     @use "niri-action" "focus-column-right"
+*   }
+* }
+*
+* Names that aren't defined (ex: "${HOME}") are passed to the shell as is.
+*
+*/
+
+use super::{children_to_commands, Command, Keyword};
+use std::collections::HashMap;
+
+// Parse
+use knus::errors::DecodeError;
+use tracing::warn;
+
+/// Variables and templates defined so far, stored in the decode context.
+#[derive(Default, Debug, Clone)]
+pub struct Vars {
+    pub vars: HashMap<String, String>,
+    /// Commands with positional placeholders (${1}, ${2}...).
+    pub templates: HashMap<String, Vec<Command>>,
+}
+
+/// Replace every "${name}" the lookup knows with its value.
+/// Other ones (ex: "${HOME}") are left as is, for the shell.
+pub fn expand(string: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut res = String::new();
+    let mut rest = string;
+    while let Some(index) = rest.find("${") {
+        res.push_str(&rest[..index]);
+        rest = &rest[index..];
+        match rest[2..]
+            .split_once('}')
+            .and_then(|(name, tail)| Some((lookup(name)?, tail)))
+        {
+            Some((value, tail)) => {
+                res.push_str(&value);
+                rest = tail;
+            }
+            None => {
+                res.push_str("${");
+                rest = &rest[2..];
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Names of the "${name}" placeholders of a string.
+pub fn placeholders(string: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = string;
+    while let Some(index) = rest.find("${") {
+        rest = &rest[index + 2..];
+        if let Some((name, _)) = rest.split_once('}') {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Whether a name follows the naming of variables (ex: "niri", "focus-left"),
+/// rather than of shell parameters (ex: "HOME").
+fn is_var_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+impl Vars {
+    /// Expand the variables of a command string.
+    /// Positional placeholders and shell parameters are kept as is.
+    pub fn expand(&self, string: &str) -> String {
+        expand(string, |name| self.vars.get(name).cloned())
+    }
+
+    /// Warn about names that look like variables but aren't defined,
+    /// they are passed to the shell as is.
+    pub fn check(&self, string: &str) {
+        for name in placeholders(string) {
+            if is_var_name(name) && !self.vars.contains_key(name) {
+                warn!(
+                    "undefined variable `{}` in {:?}, passed to the shell as is (define it in @vars before use).",
+                    name, string
+                );
+            }
+        }
+    }
+
+    /// Template commands with positional placeholders replaced by arguments.
+    /// Returns the first placeholder without argument on error.
+    pub fn apply(commands: &[Command], args: &[String]) -> Result<Vec<Command>, String> {
+        let lookup = |name: &str| {
+            let index = name.parse::<usize>().ok()?;
+            args.get(index.checked_sub(1)?).cloned()
+        };
+        let expand = |string: &str| {
+            let missing = placeholders(string)
+                .into_iter()
+                .find(|e| e.parse::<usize>().is_ok() && lookup(e).is_none());
+            match missing {
+                Some(name) => Err(name.to_owned()),
+                None => Ok(expand(string, lookup)),
+            }
+        };
+        commands
+            .iter()
+            .map(|command| match command {
+                Command::Sh(e) => Ok(Command::Sh(expand(e)?)),
+                Command::Internal(Keyword::Enter(e)) => {
+                    Ok(Command::Internal(Keyword::Enter(expand(e)?)))
+                }
+                Command::Internal(Keyword::Exit) => Ok(command.to_owned()),
+            })
+            .collect()
+    }
+}
+
+/// Store the variables of a @vars node in the decode context.
+pub fn decode_vars<S: knus::traits::ErrorSpan>(
+    node: &knus::ast::SpannedNode<S>,
+    ctx: &mut knus::decode::Context<S>,
+) -> Result<(), DecodeError<S>> {
+    let mut vars = ctx.get::<Vars>().cloned().unwrap_or_default();
+    for child in node.children() {
+        let val = child.arguments.first().ok_or_else(|| {
+            DecodeError::missing(
+                child,
+                "a variable value is required (ex: niri \"niri msg\")",
+            )
+        })?;
+        let value: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+        // Variables may refer to the ones defined before.
+        vars.check(&value);
+        let value = vars.expand(&value);
+        vars.vars.insert(child.node_name.to_string(), value);
+    }
+    ctx.set(vars);
+    Ok(())
+}
+
+/// Store the commands of a @template node in the decode context.
+pub fn decode_template<S: knus::traits::ErrorSpan>(
+    node: &knus::ast::SpannedNode<S>,
+    ctx: &mut knus::decode::Context<S>,
+) -> Result<(), DecodeError<S>> {
+    let val = node
+        .arguments
+        .first()
+        .ok_or_else(|| DecodeError::missing(node, "additional argument `name` is required"))?;
+    let name: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+    let commands = children_to_commands(node, ctx)?;
+    let mut vars = ctx.get::<Vars>().cloned().unwrap_or_default();
+    vars.templates.insert(name, commands);
+    ctx.set(vars);
+    Ok(())
+}
+
+/// Expand the variables of a command argument.
+pub fn expand_arg<S: knus::traits::ErrorSpan>(
+    value: &str,
+    ctx: &knus::decode::Context<S>,
+) -> String {
+    match ctx.get::<Vars>() {
+        Some(vars) => {
+            vars.check(value);
+            vars.expand(value)
+        }
+        None => value.to_owned(),
+    }
+}
+
+/// Commands of a @use node: a template name followed by its arguments.
+pub fn use_template<S: knus::traits::ErrorSpan>(
+    node: &knus::ast::SpannedNode<S>,
+    ctx: &mut knus::decode::Context<S>,
+) -> Result<Vec<Command>, DecodeError<S>> {
+    let mut iter_args = node.arguments.iter();
+    let val = iter_args
+        .next()
+        .ok_or_else(|| DecodeError::missing(node, "additional argument `name` is required"))?;
+    let name: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+    let mut args = vec![];
+    for val in iter_args {
+        let arg: String = knus::traits::DecodeScalar::decode(val, ctx)?;
+        args.push(expand_arg(&arg, ctx));
+    }
+    let Some(commands) = ctx.get::<Vars>().and_then(|e| e.templates.get(&name)) else {
+        ctx.emit_error(DecodeError::conversion(
+            &val.literal,
+            format!(
+                "undefined template `{}` (define it with @template before use)",
+                name
+            ),
+        ));
+        return Ok(vec![]);
+    };
+    match Vars::apply(commands, &args) {
+        Ok(e) => Ok(e),
+        Err(index) => {
+            ctx.emit_error(DecodeError::conversion(
+                &val.literal,
+                format!("template `{}` expects an argument for ${{{}}}", name, index),
+            ));
+            Ok(vec![])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use miette::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn expand_variables() {
+        let lookup = |name: &str| (name == "niri").then(|| "niri msg action".to_owned());
+        assert_eq!(
+            "niri msg action focus-column-left",
+            expand("${niri} focus-column-left", lookup)
+        );
+        assert_eq!("echo $$ ${HOME} $1", expand("echo $$ ${HOME} $1", lookup));
+        assert_eq!(vec!["niri", "1"], placeholders("${niri} ${1} $2"));
+        assert!(is_var_name("focus-left"));
+        assert!(!is_var_name("HOME"));
+    }
+
+    #[test]
+    fn parse_vars_and_templates() -> Result<()> {
+        let kdl = r#"
+            @vars {
+                niri "niri msg action"
+                focus "${niri} focus"
+            }
+            @template "niri-action" {
+                - "${niri} ${1}"
+            }
+            Super+h {
+                @press {
+                    - "${focus}-column-left"
+                    @use "niri-action" "focus-column-right"
+                }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let main = config.submaps.get("main").unwrap();
+        let args = main.binds.values().next().unwrap();
+        assert_eq!(
+            vec![
+                Command::Sh("niri msg action focus-column-left".to_owned()),
+                Command::Sh("niri msg action focus-column-right".to_owned())
+            ],
+            args.commands
+        );
+
+        let kdl = r#"
+            @template "echo" {
+                - "echo ${2}"
+            }
+            Super+h {
+                @press {
+                    @use "echo" "hi"
+                }
+            }
+        "#;
+        assert!(Config::from_kdl("test.kdl", kdl).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn keep_shell_parameters() -> Result<()> {
+        let kdl = r#"
+            @template "notify" {
+                - "notify-send ${HOME} ${1}"
+            }
+            Super+h {
+                @press {
+                    - "notify-send ${HOME} $$ ${term}"
+                    @use "notify" "hi"
+                }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let main = config.submaps.get("main").unwrap();
+        let args = main.binds.values().next().unwrap();
+        assert_eq!(
+            vec![
+                Command::Sh("notify-send ${HOME} $$ ${term}".to_owned()),
+                Command::Sh("notify-send ${HOME} hi".to_owned())
+            ],
+            args.commands
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,10 @@
-use miette::{Diagnostic, MietteHandlerOpts, Report, Result, RgbColors};
+use miette::{
+    Diagnostic, LabeledSpan, MietteHandlerOpts, NamedSource, Report, Result, RgbColors, Severity,
+    SourceCode,
+};
 use pipelight_error::PipelightError;
+use std::fmt;
 use thiserror::Error;
-use tracing::error;
 
 // Error builder
 use bon::bon;
@@ -11,19 +14,33 @@ pub enum MudrasError {
     /// Lib native error
     #[error(transparent)]
     #[diagnostic(transparent)]
-    WrapError(#[from] WrapError),
+    Wrap(#[from] WrapError),
     /// Lib native error
     #[error(transparent)]
     #[diagnostic(transparent)]
-    LibError(#[from] LibError),
+    Lib(#[from] LibError),
 
     #[error(transparent)]
     #[diagnostic(code(io::error))]
-    IoError(#[from] std::io::Error),
+    Io(#[from] std::io::Error),
 
+    #[error(transparent)]
+    #[diagnostic(code(json::error))]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    #[diagnostic(code(dbus::error))]
+    Dbus(#[from] zbus::Error),
+
+    /// Boxed, it is much larger than the other errors.
     #[error(transparent)]
     #[diagnostic(code(exec::error))]
-    PipelightError(#[from] PipelightError),
+    Pipelight(#[from] Box<PipelightError>),
+}
+impl From<PipelightError> for MudrasError {
+    fn from(e: PipelightError) -> Self {
+        Box::new(e).into()
+    }
 }
 
 /// A config error with help higher origin
@@ -70,6 +87,53 @@ impl LibError {
         }
     }
 }
+/// An error or a warning pointing at several places of a configuration file.
+#[derive(Debug, Error)]
+#[error("{}", message)]
+pub struct SpanError {
+    pub message: String,
+    pub help: String,
+    pub severity: Severity,
+    pub source_code: NamedSource<String>,
+    pub labels: Vec<LabeledSpan>,
+}
+#[bon]
+impl SpanError {
+    #[builder]
+    pub fn new(
+        msg: &str,
+        help: &str,
+        severity: Severity,
+        source_code: NamedSource<String>,
+        labels: Vec<LabeledSpan>,
+    ) -> Self {
+        Self {
+            message: msg.to_owned(),
+            help: help.to_owned(),
+            severity,
+            source_code,
+            labels,
+        }
+    }
+}
+impl Diagnostic for SpanError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("mudras::span::error"))
+    }
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(&self.help))
+    }
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source_code)
+    }
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(self.labels.iter().cloned()))
+    }
+}
+
 /// The make handler functions is executed right after the main function
 /// to set up a verbose and colorful error/panic handler.
 pub fn make_handler() -> Result<(), MudrasError> {
@@ -0,0 +1,284 @@
+/*
+* Process launchers.
+*
+* - child: processes are plain children of the daemon.
+* - systemd: processes are moved into their own transient scope
+*   of the systemd user manager, over D-Bus.
+*   Scopes are requested from a thread of their own, so that spawning never waits on the bus.
+*   Falls back to detach once the session bus can't be reached.
+* - detach: processes are detached from the daemon (setsid + double fork).
+*
+*/
+
+use super::BindId;
+use crate::config::Launcher;
+use crate::input::utils;
+use pipelight_exec::Process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+
+// D-Bus
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedObjectPath, Value};
+
+// Error
+use crate::error::MudrasError;
+use miette::Result;
+use tracing::{trace, warn};
+
+#[zbus::proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait Manager {
+    fn start_transient_unit(
+        &self,
+        name: &str,
+        mode: &str,
+        properties: &[(&str, Value<'_>)],
+        aux: &[(&str, &[(&str, Value<'_>)])],
+    ) -> zbus::Result<OwnedObjectPath>;
+}
+
+/// A freshly spawned process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spawned {
+    /// A process that can be tracked and killed.
+    Child(i32),
+    /// An intermediate process that only needs to be reaped.
+    Detached(i32),
+}
+
+/// A process to move into a transient scope.
+#[derive(Debug)]
+struct Scope {
+    unit: String,
+    pid: i32,
+    description: String,
+}
+
+#[derive(Default, Debug)]
+pub struct Spawner {
+    pub launcher: Launcher,
+    /// Scope requests, to the thread that talks to the user manager.
+    /// Started on first use.
+    scopes: Option<Sender<Scope>>,
+    /// Whether connecting to the session bus already failed.
+    no_bus: Arc<AtomicBool>,
+}
+
+impl Spawner {
+    pub fn set_launcher(&mut self, launcher: &Launcher) {
+        if &self.launcher != launcher {
+            self.launcher = launcher.to_owned();
+            // Dropping the sender ends the scope thread.
+            self.scopes = None;
+            self.no_bus = Arc::default();
+        }
+    }
+
+    pub fn spawn(&mut self, id: &BindId, stdin: &str) -> Result<Option<Spawned>, MudrasError> {
+        match self.launcher {
+            Launcher::Child => Ok(spawn_child(stdin)?.map(Spawned::Child)),
+            Launcher::Detach => Ok(spawn_detached(stdin)?.map(Spawned::Detached)),
+            Launcher::Systemd => {
+                if self.no_bus.load(Ordering::Relaxed) {
+                    return Ok(spawn_detached(stdin)?.map(Spawned::Detached));
+                }
+                let Some(pid) = spawn_child(stdin)? else {
+                    return Ok(None);
+                };
+                let scope = Scope {
+                    unit: unit_name(id, pid),
+                    pid,
+                    description: stdin.to_owned(),
+                };
+                let no_bus = &self.no_bus;
+                let scopes = self
+                    .scopes
+                    .get_or_insert_with(|| start_scopes(no_bus.clone()));
+                // The thread is gone when the bus can't be reached,
+                // the process then stays a plain child.
+                let _ = scopes.send(scope);
+                Ok(Some(Spawned::Child(pid)))
+            }
+        }
+    }
+}
+
+/// Start the thread that moves processes into their scopes, off the input path.
+fn start_scopes(no_bus: Arc<AtomicBool>) -> Sender<Scope> {
+    let (sender, receiver) = mpsc::channel::<Scope>();
+    std::thread::spawn(move || {
+        let connection = match Connection::session() {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(
+                    "couldn't connect to the session bus, detaching processes: {}",
+                    e
+                );
+                no_bus.store(true, Ordering::Relaxed);
+                return;
+            }
+        };
+        for scope in receiver {
+            let Scope {
+                unit,
+                pid,
+                description,
+            } = scope;
+            if let Err(e) = start_scope(&connection, &unit, pid, &description) {
+                warn!("couldn't move process {} into scope {}: {}", pid, unit, e);
+            }
+        }
+    });
+    sender
+}
+
+fn spawn_child(stdin: &str) -> Result<Option<i32>, MudrasError> {
+    let p = Process::new().stdin(stdin).term().background().run()?;
+    Ok(p.pid)
+}
+
+fn spawn_detached(stdin: &str) -> Result<Option<i32>, MudrasError> {
+    let p = Process::new()
+        .stdin(stdin)
+        .orphan()
+        .background()
+        .detach()
+        .run()?;
+    Ok(p.pid)
+}
+
+/// Move a running process into a new transient scope unit.
+pub fn start_scope(
+    connection: &Connection,
+    unit: &str,
+    pid: i32,
+    description: &str,
+) -> Result<(), MudrasError> {
+    let proxy = ManagerProxyBlocking::new(connection)?;
+    let pids = Value::from(vec![pid as u32]);
+    let description = Value::from(format!("mudras: {}", description.trim()));
+    let job = proxy.start_transient_unit(
+        unit,
+        "fail",
+        &[("PIDs", pids), ("Description", description)],
+        &[],
+    )?;
+    trace!("started scope {} ({})", unit, job.as_str());
+    Ok(())
+}
+
+/// Build a unique and valid systemd unit name from the bind identity.
+pub fn unit_name(id: &BindId, pid: i32) -> String {
+    let keys: Vec<String> = id
+        .sequence
+        .iter()
+        .map(|(key, _)| {
+            let name = utils::key_name(*key).to_lowercase();
+            name.trim_start_matches("key_").to_owned()
+        })
+        .collect();
+    let name = format!("mudras-{}-{}-{}", id.submap, keys.join("-"), pid);
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | ':' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}.scope", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::modifier;
+    use crate::input::utils::KeyState;
+    use evdev::KeyCode;
+    use miette::{IntoDiagnostic, Result};
+    use pretty_assertions::assert_eq;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use zbus::zvariant::OwnedValue;
+
+    /// Started units and their processes.
+    type Units = Arc<Mutex<Vec<(String, Vec<u32>)>>>;
+
+    /// A systemd manager stand-in that records transient units.
+    #[derive(Default, Clone)]
+    struct FakeManager {
+        units: Units,
+    }
+    #[zbus::interface(name = "org.freedesktop.systemd1.Manager")]
+    impl FakeManager {
+        fn start_transient_unit(
+            &self,
+            name: String,
+            _mode: String,
+            properties: Vec<(String, OwnedValue)>,
+            _aux: Vec<(String, Vec<(String, OwnedValue)>)>,
+        ) -> OwnedObjectPath {
+            let pids: Vec<u32> = properties
+                .into_iter()
+                .find(|(key, _)| key == "PIDs")
+                .map(|(_, val)| Vec::<u32>::try_from(val).unwrap())
+                .unwrap_or_default();
+            self.units.lock().unwrap().push((name, pids));
+            OwnedObjectPath::try_from("/org/freedesktop/systemd1/job/1").unwrap()
+        }
+    }
+
+    #[test]
+    fn start_scope_on_standin_manager() -> Result<()> {
+        let manager = FakeManager::default();
+        let (server, client) = UnixStream::pair().into_diagnostic()?;
+        let server = std::thread::spawn({
+            let manager = manager.clone();
+            move || {
+                zbus::blocking::connection::Builder::unix_stream(server)
+                    .server(zbus::Guid::generate())
+                    .unwrap()
+                    .p2p()
+                    .serve_at("/org/freedesktop/systemd1", manager)
+                    .unwrap()
+                    .build()
+                    .unwrap()
+            }
+        });
+        let connection = zbus::blocking::connection::Builder::unix_stream(client)
+            .p2p()
+            .build()
+            .into_diagnostic()?;
+        let _server = server.join().unwrap();
+
+        let id = BindId {
+            submap: "main".to_owned(),
+            device: None,
+            sequence: vec![
+                (KeyCode::KEY_LEFTMETA, KeyState::Pressed),
+                (KeyCode::KEY_ENTER, KeyState::Pressed),
+            ],
+        };
+        let unit = unit_name(&id, 42);
+        assert_eq!("mudras-main-leftmeta-enter-42.scope", unit);
+        let id = BindId {
+            sequence: vec![
+                (modifier::SUPER, KeyState::Pressed),
+                (KeyCode::KEY_ENTER, KeyState::Pressed),
+            ],
+            ..id
+        };
+        assert_eq!("mudras-main-super-enter-42.scope", unit_name(&id, 42));
+
+        start_scope(&connection, &unit, 42, "kitty -e fish")?;
+        assert_eq!(
+            vec![(unit, vec![42])],
+            manager.units.lock().unwrap().clone()
+        );
+        Ok(())
+    }
+}
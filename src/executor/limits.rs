@@ -0,0 +1,134 @@
+use super::BindId;
+use crate::config::BindArgs;
+use crate::input::utils;
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Window over which `max-rate` is enforced.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Per bind trigger history.
+#[derive(Default, Debug)]
+pub struct Limiter {
+    /// Accepted triggers, oldest first.
+    /// Only the ones inside the rate window and the last one are kept.
+    triggers: VecDeque<Instant>,
+    /// Triggers dropped so far.
+    pub dropped: usize,
+}
+
+impl Limiter {
+    /// Record a trigger and return whether it is allowed
+    /// by the bind cooldown and rate.
+    pub fn accept(&mut self, args: &BindArgs, now: Instant) -> bool {
+        if let Some(cooldown) = args.cooldown {
+            if let Some(last) = self.triggers.back() {
+                if now.duration_since(*last) < cooldown {
+                    self.dropped += 1;
+                    return false;
+                }
+            }
+        }
+        if let Some(max_rate) = args.max_rate {
+            let in_window = self
+                .triggers
+                .iter()
+                .filter(|e| now.duration_since(**e) < RATE_WINDOW)
+                .count();
+            if in_window >= max_rate as usize {
+                self.dropped += 1;
+                return false;
+            }
+        }
+        self.triggers.push_back(now);
+        while self.triggers.len() > 1
+            && self
+                .triggers
+                .front()
+                .is_some_and(|e| now.duration_since(*e) >= RATE_WINDOW)
+        {
+            self.triggers.pop_front();
+        }
+        true
+    }
+}
+
+/// Executor counters.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Spawned processes currently alive.
+    pub children: usize,
+    /// Triggers dropped by cooldowns, rates and the children cap.
+    pub dropped: usize,
+    pub binds: Vec<BindStats>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BindStats {
+    pub submap: String,
+    pub bind: String,
+    pub children: usize,
+    pub dropped: usize,
+}
+
+impl BindStats {
+    pub fn new(id: &BindId) -> Self {
+        let bind = id
+            .sequence
+            .iter()
+            .map(|(key, _)| utils::key_name(*key))
+            .collect::<Vec<String>>()
+            .join("+");
+        Self {
+            submap: id.submap.to_owned(),
+            bind,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::modifier;
+    use crate::input::utils::KeyState;
+    use evdev::KeyCode;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn drop_triggers_over_rate_and_cooldown() {
+        let start = Instant::now();
+        let args = BindArgs {
+            cooldown: Some(Duration::from_millis(100)),
+            max_rate: Some(3),
+            ..Default::default()
+        };
+        let mut limiter = Limiter::default();
+        let ms = |n: u64| start + Duration::from_millis(n);
+
+        assert!(limiter.accept(&args, ms(0)));
+        // Cooldown
+        assert!(!limiter.accept(&args, ms(50)));
+        assert!(limiter.accept(&args, ms(150)));
+        assert!(limiter.accept(&args, ms(300)));
+        // Rate
+        assert!(!limiter.accept(&args, ms(450)));
+        assert!(limiter.accept(&args, ms(1050)));
+        assert_eq!(2, limiter.dropped);
+    }
+
+    #[test]
+    fn name_binds_with_modifiers() {
+        let id = BindId {
+            submap: "main".to_owned(),
+            device: None,
+            sequence: vec![
+                (modifier::SUPER, KeyState::Pressed),
+                (KeyCode::KEY_ENTER, KeyState::Pressed),
+            ],
+        };
+        assert_eq!("Super+KEY_ENTER", BindStats::new(&id).bind);
+    }
+}
@@ -0,0 +1,312 @@
+/*
+* Command execution.
+*
+* Processes spawned by a bind are tracked by the bind identity
+* (submap name + key sequence) rather than by the bind itself,
+* so that instance policies keep working across configuration reloads.
+*
+* Every trigger goes through the bind limiter (cooldown, rate)
+* and the global children cap before anything is spawned.
+* Commands are then handed to the configured launcher.
+*
+*/
+
+mod launcher;
+mod limits;
+use launcher::{Spawned, Spawner};
+use limits::Limiter;
+pub use limits::{BindStats, Stats};
+
+use crate::config::utils::{name_from_key, MODIFIERS};
+use crate::config::{
+    BindArgs, BindSequence, Command, Instance, Keyword, Options, Submaps, Switches,
+};
+use crate::input::switch::{key_to_switch, SWITCH_SUBMAP};
+
+// Process manipulation
+use evdev::KeyCode;
+use rustix::io::Errno;
+use rustix::process::{kill_process, test_kill_process, waitpid, Pid, Signal, WaitOptions};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
+
+// Error
+use crate::error::MudrasError;
+use miette::Result;
+use tracing::{debug, trace, warn};
+
+/// Identifies a bind independently of its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BindId {
+    pub submap: String,
+    /// Device scope
+    pub device: Option<String>,
+    pub sequence: BindSequence,
+}
+
+/// A bind trigger recorded instead of spawned (dry run).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trigger {
+    pub id: BindId,
+    pub commands: Vec<Command>,
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Modifiers first, as written in configuration files.
+        let mut keys: Vec<KeyCode> = self.id.sequence.iter().map(|(key, _)| *key).collect();
+        keys.sort_by_key(|key| !MODIFIERS.contains(key));
+        let sequence: Vec<String> = keys.into_iter().map(name_from_key).collect();
+        write!(
+            f,
+            "  bind {:?} in submap {:?}",
+            sequence.join("+"),
+            self.id.submap
+        )?;
+        if let Some(device) = &self.id.device {
+            write!(f, " (device {:?})", device)?;
+        }
+        writeln!(f)?;
+        for command in &self.commands {
+            match command {
+                Command::Sh(e) => writeln!(f, "    spawn {:?}", e)?,
+                Command::Internal(Keyword::Enter(e)) => writeln!(f, "    @enter {:?}", e)?,
+                Command::Internal(Keyword::Exit) => writeln!(f, "    @exit")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Executor {
+    options: Options,
+    spawner: Spawner,
+    /// Processes spawned per bind.
+    pids: HashMap<BindId, Vec<i32>>,
+    /// Processes that are not tracked by any bind anymore
+    /// but still need to be reaped.
+    orphans: Vec<i32>,
+    /// Trigger history per bind.
+    limits: HashMap<BindId, Limiter>,
+    /// Triggers dropped because of the children cap.
+    capped: usize,
+    /// Triggers recorded instead of spawned, when dry running.
+    dry_run: Option<Vec<Trigger>>,
+}
+
+impl Executor {
+    /// An executor that records triggers and spawns nothing.
+    pub fn dry_run() -> Self {
+        Self {
+            dry_run: Some(vec![]),
+            ..Default::default()
+        }
+    }
+
+    /// Triggers recorded since the last call (dry run only).
+    pub fn take_triggers(&mut self) -> Vec<Trigger> {
+        self.dry_run
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn set_options(&mut self, options: &Options) {
+        self.options = options.to_owned();
+        self.spawner.set_launcher(&options.launcher);
+    }
+
+    /// Spawn the shell commands of a bind according to its limits and instance policy.
+    /// Returns false if the trigger has been dropped.
+    pub fn run(&mut self, id: &BindId, args: &BindArgs) -> Result<bool, MudrasError> {
+        self.reap();
+        let limiter = self.limits.entry(id.to_owned()).or_default();
+        if !limiter.accept(args, Instant::now()) {
+            debug!(
+                "dropped trigger of {:?} ({} dropped so far).",
+                id, limiter.dropped
+            );
+            return Ok(false);
+        }
+        if let Some(triggers) = &mut self.dry_run {
+            triggers.push(Trigger {
+                id: id.to_owned(),
+                commands: args.commands.clone(),
+            });
+            return Ok(true);
+        }
+
+        let alive = self.pids.contains_key(id);
+        match args.instance {
+            Instance::Multiple => {}
+            Instance::Single => {
+                if alive {
+                    debug!("bind already has a running instance, skipping.");
+                    return Ok(true);
+                }
+            }
+            Instance::Toggle => {
+                if alive {
+                    self.kill(id);
+                    return Ok(true);
+                }
+            }
+            Instance::Restart => {
+                if alive {
+                    self.kill(id);
+                }
+            }
+        }
+        for cmd in &args.commands {
+            if let Command::Sh(stdin) = cmd {
+                if self.children() >= self.options.max_children {
+                    self.capped += 1;
+                    warn!(
+                        "too many spawned processes ({}), dropped command {:#?}.",
+                        self.options.max_children, stdin
+                    );
+                    continue;
+                }
+                match self.spawner.spawn(id, stdin) {
+                    Ok(Some(Spawned::Child(pid))) => {
+                        trace!("spawned process {} for {:?}", pid, id);
+                        self.pids.entry(id.to_owned()).or_default().push(pid);
+                    }
+                    Ok(Some(Spawned::Detached(pid))) => {
+                        trace!("spawned detached process {} for {:?}", pid, id);
+                        self.orphans.push(pid);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        warn!("couldn't spawn command {:#?}: {}", stdin, e);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Number of spawned processes still alive.
+    pub fn children(&self) -> usize {
+        self.pids.values().map(|e| e.len()).sum::<usize>() + self.orphans.len()
+    }
+
+    pub fn stats(&mut self) -> Stats {
+        self.reap();
+        let mut binds: HashMap<BindId, BindStats> = HashMap::new();
+        for (id, limiter) in &self.limits {
+            let stats = binds.entry(id.to_owned()).or_insert(BindStats::new(id));
+            stats.dropped = limiter.dropped;
+        }
+        for (id, pids) in &self.pids {
+            let stats = binds.entry(id.to_owned()).or_insert(BindStats::new(id));
+            stats.children = pids.len();
+        }
+        let mut binds: Vec<BindStats> = binds.into_values().collect();
+        binds.sort_by(|a, b| (&a.submap, &a.bind).cmp(&(&b.submap, &b.bind)));
+        Stats {
+            children: self.children(),
+            dropped: self.capped + self.limits.values().map(|e| e.dropped).sum::<usize>(),
+            binds,
+        }
+    }
+
+    /// Send SIGTERM to every process spawned by the bind.
+    pub fn kill(&mut self, id: &BindId) {
+        if let Some(pids) = self.pids.remove(id) {
+            for pid in pids {
+                debug!("killing process {} of {:?}", pid, id);
+                if let Some(pid) = Pid::from_raw(pid) {
+                    let _ = kill_process(pid, Signal::TERM);
+                }
+                self.orphans.push(pid);
+            }
+        }
+    }
+
+    /// Stop tracking binds that do not exist anymore in the given submaps and switches.
+    /// Their processes are left running.
+    pub fn retain(&mut self, submaps: &Submaps, switches: &Switches) {
+        let exists = |id: &BindId| {
+            if id.submap == SWITCH_SUBMAP {
+                return id
+                    .sequence
+                    .first()
+                    .and_then(|(key, _)| key_to_switch(*key))
+                    .is_some_and(|e| switches.contains_key(&e));
+            }
+            submaps
+                .get(&id.submap)
+                .and_then(|submap| submap.scope(id.device.as_deref()))
+                .is_some_and(|binds| binds.contains_key(&id.sequence))
+        };
+        let ids: Vec<BindId> = self.pids.keys().filter(|e| !exists(e)).cloned().collect();
+        for id in ids {
+            if let Some(pids) = self.pids.remove(&id) {
+                self.orphans.extend(pids);
+            }
+        }
+        self.limits.retain(|id, _| exists(id));
+    }
+
+    /// Collect exited children and forget about them.
+    pub fn reap(&mut self) {
+        for pids in self.pids.values_mut() {
+            pids.retain(|pid| is_alive(*pid));
+        }
+        self.pids.retain(|_, pids| !pids.is_empty());
+        self.orphans.retain(|pid| is_alive(*pid));
+    }
+}
+
+/// Whether a process is still running.
+/// Reaps it if it is a terminated child of the daemon.
+fn is_alive(pid: i32) -> bool {
+    let Some(pid) = Pid::from_raw(pid) else {
+        return false;
+    };
+    match waitpid(Some(pid), WaitOptions::NOHANG) {
+        // Still running
+        Ok(None) => true,
+        // Exited and reaped
+        Ok(Some(_)) => false,
+        // Not a child of ours anymore
+        Err(Errno::CHILD) => test_kill_process(pid).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miette::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn toggle_kills_running_instance() -> Result<()> {
+        let mut executor = Executor::default();
+        let id = BindId {
+            submap: "main".to_owned(),
+            device: None,
+            sequence: vec![],
+        };
+        let args = BindArgs {
+            commands: vec![Command::Sh("sleep 10".to_owned())],
+            instance: Instance::Toggle,
+            ..Default::default()
+        };
+
+        executor.run(&id, &args)?;
+        let pid = executor.pids.get(&id).unwrap()[0];
+        assert!(is_alive(pid));
+
+        // Second trigger kills the process and spawns nothing.
+        executor.run(&id, &args)?;
+        assert_eq!(None, executor.pids.get(&id));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!is_alive(pid));
+        Ok(())
+    }
+}
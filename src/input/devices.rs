@@ -0,0 +1,269 @@
+use super::utils::{self, KeyboardState};
+use crate::config::{Config, DeviceFilter};
+
+use evdev::Device;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceKind {
+    Keyboard,
+    Pointer,
+    /// Switch-only devices are listened to but not grabbed.
+    Switch,
+}
+
+/// Seat of the devices that aren't part of any seat group.
+const DEFAULT_SEAT: &str = "default";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrabbedDevice {
+    pub kind: DeviceKind,
+    /// Device scope (the matching filter name).
+    pub scope: Option<String>,
+    /// Devices of the same seat share their pressed keys.
+    /// None when the device stands on its own.
+    pub seat: Option<String>,
+}
+
+/// Grabbed (or listened) devices and the rules to decide which ones to grab.
+#[derive(Default, Debug)]
+pub struct Devices {
+    pub filters: Vec<DeviceFilter>,
+    /// Grab pointers that no filter decides about.
+    pub grab_pointers: bool,
+    /// Put devices without a seat group in the default seat.
+    pub merge_devices: bool,
+    /// Grabbed devices by path.
+    pub grabbed: HashMap<String, GrabbedDevice>,
+}
+
+impl Devices {
+    pub fn new(config: &Config) -> Self {
+        let mut devices = Self::default();
+        devices.configure(config);
+        devices
+    }
+
+    pub fn configure(&mut self, config: &Config) {
+        self.filters = config.devices.clone();
+        self.grab_pointers = config.options.grab_pointers;
+        self.merge_devices = config.options.merge_devices;
+    }
+
+    /// Decide whether the device must be grabbed, and track it if so.
+    pub fn register(&mut self, path: &str, device: &Device) -> Option<DeviceKind> {
+        let info = DeviceInfo::new(path, device);
+        let filter = find_filter(&self.filters, &info);
+        if filter.is_some_and(|e| e.ignore) {
+            return None;
+        }
+        let kind = if utils::check_device_is_keyboard(device) {
+            DeviceKind::Keyboard
+        } else if utils::check_device_is_pointer(device)
+            && filter.and_then(|e| e.grab).unwrap_or(self.grab_pointers)
+        {
+            DeviceKind::Pointer
+        } else if utils::check_device_is_switch(device) {
+            DeviceKind::Switch
+        } else {
+            return None;
+        };
+        let filter = filter.cloned();
+        self.insert(path, kind, filter.as_ref());
+        Some(kind)
+    }
+
+    /// Track a device with the filter it matches.
+    pub fn insert(&mut self, path: &str, kind: DeviceKind, filter: Option<&DeviceFilter>) {
+        let seat = filter
+            .and_then(|e| e.seat.clone())
+            .or_else(|| self.merge_devices.then(|| DEFAULT_SEAT.to_owned()));
+        self.grabbed.insert(
+            path.to_owned(),
+            GrabbedDevice {
+                kind,
+                scope: filter.map(|e| e.name.clone()),
+                seat,
+            },
+        );
+    }
+
+    pub fn unregister(&mut self, path: &str) -> Option<GrabbedDevice> {
+        self.grabbed.remove(path)
+    }
+
+    pub fn kind(&self, path: &str) -> Option<DeviceKind> {
+        self.grabbed.get(path).map(|e| e.kind)
+    }
+
+    /// Device scope of a grabbed device.
+    pub fn scope(&self, path: &str) -> Option<&str> {
+        self.grabbed.get(path).and_then(|e| e.scope.as_deref())
+    }
+
+    /// State of the device merged with the states of its seat mates.
+    /// Used for bind matching only,
+    /// events are still forwarded according to the device own state.
+    pub fn seat_state(
+        &self,
+        path: &str,
+        keyboard_states: &HashMap<String, KeyboardState>,
+    ) -> KeyboardState {
+        let Some(state) = keyboard_states.get(path) else {
+            return KeyboardState::default();
+        };
+        let seat = self.grabbed.get(path).and_then(|e| e.seat.as_ref());
+        let mates = keyboard_states
+            .iter()
+            .filter(|(other, _)| {
+                other.as_str() != path
+                    && seat.is_some()
+                    && self.grabbed.get(*other).and_then(|e| e.seat.as_ref()) == seat
+            })
+            .map(|(_, e)| e);
+        state.merge(mates)
+    }
+}
+
+/// Device properties used by device filters.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    pub path: String,
+    pub name: String,
+    pub phys: String,
+    pub vendor: u16,
+    pub product: u16,
+    /// Udev properties
+    pub properties: HashMap<String, String>,
+}
+
+impl DeviceInfo {
+    pub fn new(path: &str, device: &Device) -> Self {
+        let input_id = device.input_id();
+        Self {
+            path: path.to_owned(),
+            name: device.name().unwrap_or_default().to_owned(),
+            phys: device.physical_path().unwrap_or_default().to_owned(),
+            vendor: input_id.vendor(),
+            product: input_id.product(),
+            properties: udev_properties(path),
+        }
+    }
+}
+
+/// Read udev properties of a device node (ex: "/dev/input/event3").
+fn udev_properties(path: &str) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    let Some(sysname) = Path::new(path).file_name() else {
+        return properties;
+    };
+    let syspath = PathBuf::from("/sys/class/input").join(sysname);
+    if let Ok(device) = tokio_udev::Device::from_syspath(&syspath) {
+        for property in device.properties() {
+            properties.insert(
+                property.name().to_string_lossy().to_string(),
+                property.value().to_string_lossy().to_string(),
+            );
+        }
+    }
+    properties
+}
+
+/// Return the first filter matching the device.
+pub fn find_filter<'a>(filters: &'a [DeviceFilter], info: &DeviceInfo) -> Option<&'a DeviceFilter> {
+    filters.iter().find(|filter| matches(filter, info))
+}
+
+/// Whether a device matches every criteria of the filter.
+pub fn matches(filter: &DeviceFilter, info: &DeviceInfo) -> bool {
+    let glob = |pattern: &Option<String>, value: &str| match pattern {
+        Some(pattern) => glob::Pattern::new(pattern).is_ok_and(|e| e.matches(value)),
+        None => true,
+    };
+    glob(&filter.device_name, &info.name)
+        && glob(&filter.phys, &info.phys)
+        && filter
+            .vendor_product
+            .is_none_or(|ids| ids == (info.vendor, info.product))
+        && filter
+            .udev
+            .iter()
+            .all(|(key, value)| info.properties.get(key) == Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::utils::KeyState;
+    use evdev::KeyCode;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn match_device_filters() {
+        let info = DeviceInfo {
+            name: "Yubico YubiKey OTP+FIDO+CCID".to_owned(),
+            phys: "usb-0000:00:14.0-1/input0".to_owned(),
+            vendor: 0x1050,
+            product: 0x0407,
+            properties: HashMap::from([("ID_BUS".to_owned(), "usb".to_owned())]),
+            ..Default::default()
+        };
+        let filters = vec![
+            DeviceFilter {
+                name: "pad".to_owned(),
+                vendor_product: Some((0x1234, 0x5678)),
+                ..Default::default()
+            },
+            DeviceFilter {
+                name: "yubikey".to_owned(),
+                device_name: Some("Yubico*".to_owned()),
+                udev: vec![("ID_BUS".to_owned(), "usb".to_owned())],
+                ignore: true,
+                ..Default::default()
+            },
+        ];
+        let filter = find_filter(&filters, &info).unwrap();
+        assert_eq!("yubikey", filter.name);
+    }
+
+    #[test]
+    fn merge_seat_states() {
+        let grabbed = |seat: Option<&str>| GrabbedDevice {
+            kind: DeviceKind::Keyboard,
+            scope: None,
+            seat: seat.map(|e| e.to_owned()),
+        };
+        let devices = Devices {
+            grabbed: HashMap::from([
+                ("laptop".to_owned(), grabbed(Some("default"))),
+                ("keypad".to_owned(), grabbed(Some("default"))),
+                ("pad".to_owned(), grabbed(None)),
+            ]),
+            ..Default::default()
+        };
+        let mut keyboard_states: HashMap<String, KeyboardState> = HashMap::new();
+        for (path, key) in [
+            ("laptop", KeyCode::KEY_LEFTMETA),
+            ("keypad", KeyCode::KEY_ENTER),
+            ("pad", KeyCode::KEY_LEFTCTRL),
+        ] {
+            keyboard_states
+                .entry(path.to_owned())
+                .or_default()
+                .update(key, &KeyState::Pressed);
+        }
+
+        let state = devices.seat_state("keypad", &keyboard_states);
+        let mut keys: Vec<KeyCode> = state.current.keys.into_keys().collect();
+        keys.sort();
+        assert_eq!(vec![KeyCode::KEY_ENTER, KeyCode::KEY_LEFTMETA], keys);
+
+        // Devices out of any seat stand on their own.
+        let state = devices.seat_state("pad", &keyboard_states);
+        let keys: Vec<KeyCode> = state.current.keys.into_keys().collect();
+        assert_eq!(vec![KeyCode::KEY_LEFTCTRL], keys);
+    }
+}
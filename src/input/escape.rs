@@ -0,0 +1,91 @@
+/*
+* Emergency escape.
+*
+* Holding the escape chord ungrabs every device and pauses bind matching,
+* so that a misbehaving daemon can't lock the user out of its own machine.
+* Holding it again grabs devices back once every key is released.
+*
+*/
+
+use super::modifier;
+use super::utils::KeyboardState;
+use crate::config::Options;
+
+use evdev::KeyCode;
+use std::time::{Duration, Instant};
+
+#[derive(Default, Debug)]
+pub struct Escape {
+    pub keys: Vec<KeyCode>,
+    /// How long the chord must be held.
+    pub hold: Duration,
+    /// When the chord started being held.
+    since: Option<Instant>,
+    /// Whether the chord already fired during the current hold.
+    fired: bool,
+}
+
+impl Escape {
+    pub fn new(options: &Options) -> Self {
+        let mut escape = Self::default();
+        escape.set_options(options);
+        escape
+    }
+
+    pub fn set_options(&mut self, options: &Options) {
+        self.keys = options.escape.clone();
+        self.hold = options.escape_hold;
+    }
+
+    /// Follow the chord state on every key event.
+    pub fn update(&mut self, state: &KeyboardState, now: Instant) {
+        let held = !self.keys.is_empty()
+            && self
+                .keys
+                .iter()
+                .all(|key| modifier::is_pressed(*key, &state.current));
+        if !held {
+            self.since = None;
+            self.fired = false;
+        } else if self.since.is_none() {
+            self.since = Some(now);
+        }
+    }
+
+    /// Whether the chord has just been held long enough.
+    /// Fires once per hold.
+    pub fn fire(&mut self, now: Instant) -> bool {
+        match self.since {
+            Some(since) if !self.fired && now.duration_since(since) >= self.hold => {
+                self.fired = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::utils::KeyState;
+
+    #[test]
+    fn fire_once_when_held_long_enough() {
+        let mut escape = Escape::new(&Options::default());
+        let mut state = KeyboardState::default();
+        for key in &escape.keys.clone() {
+            state.update(*key, &KeyState::Pressed);
+        }
+        let start = Instant::now();
+        escape.update(&state, start);
+        assert!(!escape.fire(start + Duration::from_secs(1)));
+        assert!(escape.fire(start + Duration::from_secs(2)));
+        assert!(!escape.fire(start + Duration::from_secs(3)));
+
+        // Releasing a key of the chord rearms it.
+        state.update(KeyCode::KEY_ESC, &KeyState::Released);
+        escape.update(&state, start + Duration::from_secs(3));
+        assert!(!escape.fire(start + Duration::from_secs(6)));
+    }
+}
@@ -0,0 +1,167 @@
+/*
+* Device diagnostics.
+*
+* Describes every input device as mudras sees it:
+* properties, classification, matching filter and grab decision.
+*
+*/
+
+use super::devices::{find_filter, DeviceInfo, DeviceKind, Devices};
+use super::utils;
+
+use crate::config::Config;
+
+use evdev::{Device, EventType};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::ErrorKind;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DeviceReport {
+    pub path: String,
+    pub name: String,
+    pub phys: String,
+    /// Vendor and product ids (ex: "1234:abcd").
+    pub vendor_product: String,
+    /// Supported event types, with the number of codes when relevant.
+    pub capabilities: Vec<String>,
+    pub keyboard: bool,
+    pub pointer: bool,
+    pub switch: bool,
+    /// Another process holds an exclusive grab on the device.
+    pub busy: bool,
+    /// Name of the first matching `@device` filter.
+    pub filter: Option<String>,
+    /// How mudras would handle the device, if at all.
+    pub kind: Option<DeviceKind>,
+}
+
+impl DeviceReport {
+    pub fn new(path: &str, device: &mut Device, devices: &mut Devices) -> Self {
+        let info = DeviceInfo::new(path, device);
+        let count = |e: Option<usize>| e.map(|e| format!("({})", e)).unwrap_or_default();
+        let capabilities = device
+            .supported_events()
+            .iter()
+            .map(|e| {
+                let codes = match e {
+                    EventType::KEY => count(device.supported_keys().map(|e| e.iter().count())),
+                    EventType::RELATIVE => {
+                        count(device.supported_relative_axes().map(|e| e.iter().count()))
+                    }
+                    EventType::ABSOLUTE => {
+                        count(device.supported_absolute_axes().map(|e| e.iter().count()))
+                    }
+                    EventType::SWITCH => {
+                        count(device.supported_switches().map(|e| e.iter().count()))
+                    }
+                    _ => String::new(),
+                };
+                format!("{:?}{}", e, codes)
+            })
+            .collect();
+        Self {
+            path: path.to_owned(),
+            filter: find_filter(&devices.filters, &info).map(|e| e.name.clone()),
+            name: info.name,
+            phys: info.phys,
+            vendor_product: format!("{:04x}:{:04x}", info.vendor, info.product),
+            capabilities,
+            keyboard: utils::check_device_is_keyboard(device),
+            pointer: utils::check_device_is_pointer(device),
+            switch: utils::check_device_is_switch(device),
+            busy: is_busy(device),
+            kind: devices.register(path, device),
+        }
+    }
+}
+
+/// Whether another process grabbed the device.
+/// The device is grabbed and released right away to find out.
+fn is_busy(device: &mut Device) -> bool {
+    match device.grab() {
+        Ok(()) => {
+            let _ = device.ungrab();
+            false
+        }
+        Err(e) => e.kind() == ErrorKind::ResourceBusy,
+    }
+}
+
+/// Describe every input device, sorted by event number.
+pub fn reports(config: &Config) -> Vec<DeviceReport> {
+    let mut devices = Devices::new(config);
+    let mut reports: Vec<DeviceReport> = evdev::enumerate()
+        .map(|(path, mut device)| {
+            DeviceReport::new(&path.to_string_lossy(), &mut device, &mut devices)
+        })
+        .collect();
+    let number = |e: &DeviceReport| {
+        e.path
+            .trim_start_matches("/dev/input/event")
+            .parse::<u32>()
+            .unwrap_or(u32::MAX)
+    };
+    reports.sort_by_key(number);
+    reports
+}
+
+impl fmt::Display for DeviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes = |e: bool| if e { "yes" } else { "no" };
+        writeln!(f, "{} {:?}", self.path, self.name)?;
+        writeln!(f, "  phys: {}", self.phys)?;
+        writeln!(f, "  vendor-product: {}", self.vendor_product)?;
+        writeln!(f, "  capabilities: {}", self.capabilities.join(" "))?;
+        writeln!(
+            f,
+            "  keyboard: {}, pointer: {}, switch: {}",
+            yes(self.keyboard),
+            yes(self.pointer),
+            yes(self.switch)
+        )?;
+        writeln!(f, "  grabbed by another process: {}", yes(self.busy))?;
+        match &self.filter {
+            Some(e) => writeln!(f, "  filter: {:?}", e)?,
+            None => writeln!(f, "  filter: none")?,
+        }
+        let decision = match self.kind {
+            Some(DeviceKind::Keyboard) => "grab as keyboard",
+            Some(DeviceKind::Pointer) => "grab as pointer",
+            Some(DeviceKind::Switch) => "listen to switches",
+            None if self.filter.is_some() && (self.keyboard || self.pointer) => {
+                "skip (filtered out)"
+            }
+            None if self.pointer => "skip (grab-pointers is off)",
+            None => "skip",
+        };
+        writeln!(f, "  mudras: {}", decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miette::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn display_device_report() -> Result<()> {
+        let report = DeviceReport {
+            path: "/dev/input/event4".to_owned(),
+            name: "Logitech USB Receiver".to_owned(),
+            vendor_product: "046d:c52b".to_owned(),
+            capabilities: vec!["SYNCHRONIZATION".to_owned(), "KEY(16)".to_owned()],
+            pointer: true,
+            ..Default::default()
+        };
+        let text = report.to_string();
+        assert!(text.contains("capabilities: SYNCHRONIZATION KEY(16)"));
+        assert!(text.ends_with("mudras: skip (grab-pointers is off)\n"));
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(report, serde_json::from_str(&json).unwrap());
+        Ok(())
+    }
+}
@@ -1,134 +1,125 @@
-use super::utils::{self, KeyState, KeyboardState, SubmapState};
+use super::devices::DeviceKind;
+use super::pipeline::Pipeline;
+use super::sink::Sinks;
+use super::source::{DeviceSource, EventSource};
 use super::virtuals;
 
-use crate::config::{self, Bind, Config};
+use crate::config::Config;
 use crate::input::udev::handle_udev;
+use crate::server::events::Event;
 use crate::server::Server;
 
 // Keyboard
-use evdev::{AttributeSet, Device, EventStream, EventSummary, KeyCode};
-use std::collections::HashMap;
-use tokio::time::{sleep, Instant};
-use tokio::{pin, select, sync::mpsc};
-use tokio_stream::{StreamExt, StreamMap};
-use tokio_udev::{AsyncMonitorSocket, EventType, MonitorBuilder};
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio_stream::StreamExt;
+use tokio_udev::{AsyncMonitorSocket, MonitorBuilder};
 
 // Error
-use crate::error::{LibError, MudrasError, WrapError};
-use miette::{Error, Result};
-use tracing::{debug, error, info, trace, warn};
+use crate::error::MudrasError;
+use miette::Result;
+use tracing::{debug, error, info};
 
 impl Server {
-    pub async fn listen_keyboard(&self, config: &Config) -> Result<(), MudrasError> {
-        // Collect keyboard devices
-        let keyboard_devices: Vec<_> = evdev::enumerate()
-            .filter(|(_path, device)| utils::check_device_is_keyboard(device))
-            .collect();
-        if keyboard_devices.is_empty() {
+    /// Returns the exit code once asked to quit.
+    pub async fn listen_keyboard(&self, config: &Config) -> Result<i32, MudrasError> {
+        // Collect keyboard, pointer and switch devices
+        let mut pipeline = Pipeline::new(config);
+        let mut source = DeviceSource::default();
+        for (path, mut device) in evdev::enumerate() {
+            let path = path.to_str().unwrap();
+            if let Some(kind) = pipeline.devices.register(path, &device) {
+                if kind != DeviceKind::Switch {
+                    let _ = device.grab();
+                }
+                source
+                    .streams
+                    .insert(path.to_string(), device.into_event_stream()?);
+            }
+        }
+        let count = |kind: DeviceKind| {
+            pipeline
+                .devices
+                .grabbed
+                .values()
+                .filter(|e| e.kind == kind)
+                .count()
+        };
+        let keyboards = count(DeviceKind::Keyboard);
+        if keyboards == 0 {
             let message = "No valid keyboard device was detected!";
-            let help = "";
-            let err = LibError::builder().msg(message).help(help).build();
             error!("{}", message);
         }
 
-        debug!("{} keyboard device(s) detected.", keyboard_devices.len());
-
-        // Collect pointer devices
-        // (Not used!)
-        let pointer_devices: Vec<_> = evdev::enumerate()
-            .filter(|(_path, device)| utils::check_device_is_pointer(device))
-            .collect();
-
-        // Apparently, having a single uinput device with keys, relative axes and switches
-        // prevents some libraries to listen to these events. The easy fix is to have separate
-        // virtual devices, one for keys and relative axes (`uinput_device`) and another one
-        // just for switches (`uinput_switches_device`).
-        let mut virtual_keyboard = virtuals::create_keyboard()?;
-        let mut virtual_pointer = virtuals::create_pointer()?;
-        let mut virtual_switch = virtuals::create_switch()?;
+        debug!("{} keyboard device(s) detected.", keyboards);
+        debug!("{} pointer device(s) detected.", count(DeviceKind::Pointer));
 
-        // Per keyboard State
-        let mut keyboard_stream_map: StreamMap<String, EventStream> = StreamMap::new();
-        let mut keyboard_states = HashMap::new();
-        for (path, mut device) in keyboard_devices.into_iter() {
-            let _ = device.grab();
-            let path = path.to_str().unwrap();
-            keyboard_stream_map.insert(path.to_string(), device.into_event_stream()?);
-            keyboard_states.insert(path.to_string(), KeyboardState::default());
-        }
-
-        // Bindings state
-        let mut submaps_state = SubmapState {
-            submaps: config.submaps.clone(),
-            ..Default::default()
+        let mut sinks = Sinks {
+            keyboard: virtuals::create_keyboard()?,
+            pointer: virtuals::create_pointer()?,
+            switch: virtuals::create_switch()?,
         };
+        let mut watchdog = tokio::time::interval(Duration::from_millis(250));
 
+        let mut events = self.events.sender.subscribe();
         let mut udev =
             AsyncMonitorSocket::new(MonitorBuilder::new()?.match_subsystem("input")?.listen()?)?;
 
         loop {
             select! {
-                // Device detection
-                // Adds every keyboard to the global stream map.
-                Some(Ok(event)) = udev.next() => {
-                    handle_udev(event, &mut keyboard_stream_map)?;
-                }
-                Some((path, Ok(event))) = keyboard_stream_map.next() => {
-                    match event.destructure() {
-                        EventSummary::Key(_type, keycode, value) => {
-                            let key_state = match value {
-                                1 => KeyState::Pressed,
-                                0 => KeyState::Released,
-                                _ => KeyState::Undefined,
-                            };
-                            match key_state {
-                                KeyState::Pressed | KeyState::Released => {
-                                    // trace!("key={:#?},state={:#?}", keycode, state);
-
-                                    if let Some(keyboard_state) = keyboard_states.get_mut(&path) {
-                                        // Update keyboard representation state.
-                                        keyboard_state.previous = keyboard_state.current.clone();
-                                        for (key,value) in &keyboard_state.current.keys.clone() {
-                                            if value == &KeyState::Released {
-                                                keyboard_state.current.keys.remove(&key);
-                                            }
-                                        }
-                                        keyboard_state.current.keys.insert(keycode,key_state.clone());
-
-                                        // Grab event if a modifier of the submap is pressed.
-                                        let name = submaps_state.current.clone();
-                                        let submap = submaps_state.submaps.get(&name).unwrap();
-
-
-                                        // Test if any submap modifier is pressed/released.
-                                        let current_pressed_keys: Vec<KeyCode> = keyboard_state.current.keys.iter().filter_map(|(code, state)| {
-                                            if state == &KeyState::Pressed {
-                                                Some(code.to_owned())
-                                            }
-                                            else {
-                                                None
-                                            }
-                                        }).collect();
-                                        let mods = submap.mods.clone();
-                                        let has_any_mod = mods.iter().any(|modifier| current_pressed_keys.contains(modifier));
-
-                                        if !has_any_mod {
-                                           virtual_keyboard.emit(&[event]).unwrap();
-                                        }
-                                        // Trigger action.
-                                        utils::trigger_action(&mut submaps_state,keyboard_state, &key_state, &mut virtual_keyboard, event).unwrap();
-                                    }
+                Ok(event) = events.recv() => {
+                    match event {
+                        Event::Quit(code) => {
+                            // Release keys, then grabbed devices, then virtual devices.
+                            pipeline.release_keys(&mut sinks);
+                            source.set_grab(&pipeline.devices, false);
+                            drop(source);
+                            drop(sinks);
+                            info!("Devices released, exiting.");
+                            return Ok(code);
+                        }
+                        Event::Pause(timeout) => pipeline.pause(timeout, &mut source, &mut sinks),
+                        Event::Resume => pipeline.pause.resume(),
+                        Event::Reload => {
+                            let config = self.config.read().unwrap().clone();
+                            let mut executor = self.executor.lock().unwrap();
+                            executor.set_options(&config.options);
+                            executor.retain(&config.submaps, &config.switches);
+                            pipeline.configure(&config);
+
+                            // Apply new device filters to grabbed devices.
+                            let released: Vec<(String, String)> = source
+                                .streams
+                                .iter()
+                                .filter(|(path, stream)| pipeline.devices.register(path, stream.device()).is_none())
+                                .map(|(path, stream)| (path.to_owned(), stream.device().name().unwrap_or("[unknown]").to_owned()))
+                                .collect();
+                            for (path, name) in released {
+                                source.streams.remove(&path);
+                                if let Some(device) = pipeline.devices.unregister(&path) {
+                                    pipeline.remove_device(&path, &device, &mut sinks);
                                 }
-                                _ => {}
+                                info!("released device '{}' at '{}'.", name, path);
                             }
+                            info!("Configuration reloaded.");
                         }
-                        EventSummary::Switch(_, _, _) => {
-                            virtual_switch.emit(&[event]).unwrap();
-                        }
-                        _ => {
-                            virtual_keyboard.emit(&[event]).unwrap();
-                        }
-                    };
+                        _ => {}
+                    }
+                }
+                // Device detection
+                // Adds every keyboard and pointer to the global stream map.
+                Some(Ok(event)) = udev.next() => {
+                    let grab = !pipeline.pause.paused;
+                    if let Some((path, device)) = handle_udev(event, &mut source.streams, &mut pipeline.devices, grab)? {
+                        pipeline.remove_device(&path, &device, &mut sinks);
+                    }
+                }
+                _ = watchdog.tick() => {
+                    pipeline.tick(Instant::now(), &mut source, &mut sinks)?;
+                }
+                Some((path, event)) = source.next_event() => {
+                    let mut executor = self.executor.lock().unwrap();
+                    pipeline.handle(&path, event, &mut executor, &mut source, &mut sinks)?;
                 }
             }
         }
@@ -1,6 +1,29 @@
 // Listen to keyboard events
+pub mod devices;
+// Emergency ungrab
+pub mod escape;
+// Device diagnostics
+pub mod inspect;
 mod keyboard;
-mod virtuals;
+// Key names monitor
+pub mod monitor;
+// Event flow from sources to sinks
+pub mod pipeline;
+// Paused state
+pub mod pause;
+// Left/right agnostic modifiers
+pub mod modifier;
+// Mouse buttons and wheel
+pub mod pointer;
+// Input recordings
+pub mod record;
+pub mod sink;
+// Dry runs
+pub mod simulate;
+pub mod source;
+pub mod virtuals;
+// Lid, tablet mode, jacks...
+pub mod switch;
 // Hot plug device detection
 mod udev;
 
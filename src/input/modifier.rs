@@ -0,0 +1,139 @@
+/*
+* Modifiers.
+*
+* Super, Ctrl, Alt and Shift written without a side match either side.
+* They are pseudo keys outside of the evdev key range,
+* that pressed side keys stand for when binds are matched.
+* Side specific names (ex: "Super_L") only match their own key.
+*
+* Modifiers are always known keys, every other key of a bind is one of its triggers.
+* Modifiers are forwarded to applications while held,
+* triggers are swallowed once they complete a bind, and held while on their way to one.
+*
+*/
+
+use super::utils::{KeyState, State};
+use crate::config::BindSequence;
+
+use evdev::KeyCode;
+
+/// Modifier pseudo keys start after the wheel ones.
+pub const CTRL: KeyCode = KeyCode::new(0x310);
+pub const SHIFT: KeyCode = KeyCode::new(0x311);
+pub const ALT: KeyCode = KeyCode::new(0x312);
+pub const SUPER: KeyCode = KeyCode::new(0x313);
+
+/// Modifiers and the side keys they match, left first.
+const SIDES: &[(KeyCode, [KeyCode; 2])] = &[
+    (CTRL, [KeyCode::KEY_LEFTCTRL, KeyCode::KEY_RIGHTCTRL]),
+    (SHIFT, [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_RIGHTSHIFT]),
+    (ALT, [KeyCode::KEY_LEFTALT, KeyCode::KEY_RIGHTALT]),
+    (SUPER, [KeyCode::KEY_LEFTMETA, KeyCode::KEY_RIGHTMETA]),
+];
+
+/// Keys a bind key matches: both sides of a modifier, the key itself otherwise.
+pub fn sides(key: KeyCode) -> Vec<KeyCode> {
+    match SIDES.iter().find(|(e, _)| *e == key) {
+        Some((_, sides)) => sides.to_vec(),
+        None => vec![key],
+    }
+}
+
+/// Whether a key is a modifier, of either side or side specific.
+pub fn is_modifier(key: KeyCode) -> bool {
+    SIDES
+        .iter()
+        .any(|(e, sides)| *e == key || sides.contains(&key))
+}
+
+/// Keys that trigger a bind: its non modifier keys,
+/// or all of them for binds made of modifiers only (ex: "Super" on release).
+pub fn triggers(keys: &[KeyCode]) -> Vec<KeyCode> {
+    let triggers: Vec<KeyCode> = keys.iter().filter(|e| !is_modifier(**e)).copied().collect();
+    match triggers.is_empty() {
+        true => keys.to_vec(),
+        false => triggers,
+    }
+}
+
+/// Whether a pressed key matches a bind key.
+pub fn matches(bind_key: KeyCode, key: KeyCode) -> bool {
+    bind_key == key || modifier(key) == Some(bind_key)
+}
+
+/// Modifier a side key stands for.
+pub fn modifier(key: KeyCode) -> Option<KeyCode> {
+    SIDES
+        .iter()
+        .find(|(_, sides)| sides.contains(&key))
+        .map(|(e, _)| *e)
+}
+
+/// Key to press for a bind key: the left side of a modifier, the key itself otherwise.
+pub fn physical(key: KeyCode) -> KeyCode {
+    sides(key)[0]
+}
+
+/// Whether a bind key is pressed, on either side for modifiers.
+pub fn is_pressed(key: KeyCode, state: &State) -> bool {
+    sides(key)
+        .into_iter()
+        .chain([key])
+        .any(|e| state.keys.get(&e) == Some(&KeyState::Pressed))
+}
+
+/// Bind sequences a keyboard sequence matches, by order of precedence:
+/// side keys as they are first, then replaced by their modifier.
+/// Sequences are sorted like binds.
+pub fn variants(sequence: &BindSequence) -> Vec<BindSequence> {
+    let mut res: Vec<BindSequence> = vec![vec![]];
+    for (key, state) in sequence {
+        let mut keys = vec![*key];
+        keys.extend(modifier(*key));
+        res = keys
+            .iter()
+            .flat_map(|key| {
+                res.iter().map(move |e| {
+                    let mut e = e.clone();
+                    e.push((*key, state.clone()));
+                    e
+                })
+            })
+            .collect();
+    }
+    for e in res.iter_mut() {
+        e.sort_by_key(|a| a.0);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn match_either_side() {
+        let sequence = vec![
+            (KeyCode::KEY_ENTER, KeyState::Pressed),
+            (KeyCode::KEY_RIGHTMETA, KeyState::Pressed),
+        ];
+        assert_eq!(
+            vec![
+                sequence.clone(),
+                vec![
+                    (KeyCode::KEY_ENTER, KeyState::Pressed),
+                    (SUPER, KeyState::Pressed)
+                ],
+            ],
+            variants(&sequence)
+        );
+
+        let mut state = State::default();
+        state.keys.insert(KeyCode::KEY_RIGHTCTRL, KeyState::Pressed);
+        assert!(is_pressed(CTRL, &state));
+        assert!(is_pressed(KeyCode::KEY_RIGHTCTRL, &state));
+        assert!(!is_pressed(KeyCode::KEY_LEFTCTRL, &state));
+        assert_eq!(KeyCode::KEY_LEFTMETA, physical(SUPER));
+    }
+}
@@ -0,0 +1,180 @@
+/*
+* Key monitor.
+*
+* Prints pressed keys with their configuration names,
+* the current chord ready to paste in a config file,
+* and the binds it would trigger.
+*
+* Devices are not grabbed, matching is a dry run through the regular pipeline.
+*
+*/
+
+use super::pipeline::Pipeline;
+use super::pointer::wheel_to_key;
+use super::record::open_devices;
+use super::sink::{FakeSink, Sinks};
+use super::source::ReplaySource;
+
+use crate::config::utils::name_from_key;
+use crate::config::Config;
+use crate::executor::Executor;
+
+use evdev::{Device, EventStream, EventSummary, InputEvent, KeyCode};
+use std::fmt::Write;
+use tokio_stream::{StreamExt, StreamMap};
+
+// Error
+use crate::error::MudrasError;
+use miette::Result;
+
+#[derive(Default, Debug)]
+pub struct Monitor {
+    /// Pressed keys of every device, in press order.
+    pressed: Vec<KeyCode>,
+    /// Bind matching, when a configuration is given.
+    pipeline: Option<Pipeline>,
+    executor: Executor,
+    source: ReplaySource,
+    sinks: Sinks<FakeSink>,
+}
+
+impl Monitor {
+    pub fn new(config: Option<&Config>) -> Self {
+        Self {
+            pipeline: config.map(Pipeline::new),
+            executor: Executor::dry_run(),
+            ..Default::default()
+        }
+    }
+
+    /// Track a device the way the daemon would.
+    pub fn register(&mut self, path: &str, device: &Device) {
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.devices.register(path, device);
+        }
+    }
+
+    /// Describe an event of the device at path.
+    pub fn handle(&mut self, path: &str, event: InputEvent) -> Result<String, MudrasError> {
+        let mut res = String::new();
+        match event.destructure() {
+            EventSummary::Key(_, key, 1) => {
+                if !self.pressed.contains(&key) {
+                    self.pressed.push(key);
+                }
+                let _ = writeln!(res, "{} down  chord: {}", name_from_key(key), self.chord());
+            }
+            EventSummary::Key(_, key, 0) => {
+                self.pressed.retain(|e| *e != key);
+                let _ = writeln!(res, "{} up", name_from_key(key));
+            }
+            EventSummary::RelativeAxis(_, axis, value) => {
+                if let Some(key) = wheel_to_key(axis, value) {
+                    let chord: Vec<String> = self
+                        .pressed
+                        .iter()
+                        .chain([&key])
+                        .map(|e| name_from_key(*e))
+                        .collect();
+                    let _ = writeln!(res, "{}  chord: {}", name_from_key(key), chord.join("+"));
+                }
+            }
+            _ => {}
+        }
+        if let Some(pipeline) = &mut self.pipeline {
+            let before = pipeline.submaps_state.current.clone();
+            pipeline.handle(
+                path,
+                event,
+                &mut self.executor,
+                &mut self.source,
+                &mut self.sinks,
+            )?;
+            for trigger in self.executor.take_triggers() {
+                let _ = write!(res, "{}", trigger);
+            }
+            let after = &pipeline.submaps_state.current;
+            if &before != after {
+                let _ = writeln!(res, "  submap {:?} -> {:?}", before, after);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Pressed keys in configuration syntax (ex: "Super_L+Shift_L+n").
+    pub fn chord(&self) -> String {
+        let names: Vec<String> = self.pressed.iter().map(|e| name_from_key(*e)).collect();
+        names.join("+")
+    }
+}
+
+/// Print the events of the given devices (every keyboard and pointer by default)
+/// until interrupted.
+pub async fn monitor(paths: &[String], config: Option<&Config>) -> Result<(), MudrasError> {
+    let mut monitor = Monitor::new(config);
+    let mut streams: StreamMap<String, EventStream> = StreamMap::new();
+    for (path, device) in open_devices(paths)? {
+        println!(
+            "listening to '{}' at '{}'.",
+            device.name().unwrap_or("[unknown]"),
+            path
+        );
+        monitor.register(&path, &device);
+        streams.insert(path, device.into_event_stream()?);
+    }
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            Some((path, Ok(event))) = streams.next() => {
+                print!("{}", monitor.handle(&path, event)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::devices::DeviceKind;
+    use evdev::EventType;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn print_chord_and_matching_bind() -> Result<()> {
+        let config = Config::from_kdl(
+            "test.kdl",
+            r#"
+            Super+Shift+N {
+                @press {
+                    - "notify-send hello"
+                }
+            }
+            "#,
+        )?;
+        let mut monitor = Monitor::new(Some(&config));
+        if let Some(pipeline) = &mut monitor.pipeline {
+            pipeline
+                .devices
+                .insert("keyboard", DeviceKind::Keyboard, None);
+        }
+        let key = |code: KeyCode, value| InputEvent::new(EventType::KEY.0, code.code(), value);
+
+        monitor.handle("keyboard", key(KeyCode::KEY_LEFTMETA, 1))?;
+        monitor.handle("keyboard", key(KeyCode::KEY_LEFTSHIFT, 1))?;
+        let res = monitor.handle("keyboard", key(KeyCode::KEY_N, 1))?;
+        assert_eq!("Super_L+Shift_L+n", monitor.chord());
+        assert_eq!(
+            concat!(
+                "n down  chord: Super_L+Shift_L+n\n",
+                "  bind \"Shift+Super+n\" in submap \"main\"\n",
+                "    spawn \"notify-send hello\"\n"
+            ),
+            res
+        );
+        let res = monitor.handle("keyboard", key(KeyCode::KEY_N, 0))?;
+        assert_eq!("n up\n", res);
+        assert_eq!("Super_L+Shift_L", monitor.chord());
+        Ok(())
+    }
+}
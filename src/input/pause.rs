@@ -0,0 +1,92 @@
+/*
+* Paused state.
+*
+* While paused, held devices are ungrabbed from the very handles that grabbed them,
+* nothing is matched nor forwarded, and keys are only followed
+* so that devices can be grabbed back once every key is released
+* (grabbing a device while a key is down would leave it stuck for the compositor).
+*
+*/
+
+use std::time::{Duration, Instant};
+use tracing::info;
+
+#[derive(Default, Debug)]
+pub struct Pause {
+    /// Devices are released, nothing is matched nor forwarded.
+    pub paused: bool,
+    /// Devices are grabbed back once every key is released.
+    pub resuming: bool,
+    /// Auto-resume deadline.
+    pub until: Option<Instant>,
+}
+
+impl Pause {
+    /// Returns true if devices must be released.
+    pub fn pause(&mut self, timeout: Option<Duration>, now: Instant) -> bool {
+        self.until = timeout.map(|e| now + e);
+        self.resuming = false;
+        if self.paused {
+            return false;
+        }
+        self.paused = true;
+        match timeout {
+            Some(timeout) => info!(
+                "paused, resuming in {}.",
+                humantime::format_duration(timeout)
+            ),
+            None => info!("paused."),
+        }
+        true
+    }
+
+    /// Ask to resume once every key is released.
+    pub fn resume(&mut self) {
+        if self.paused && !self.resuming {
+            self.resuming = true;
+            self.until = None;
+            info!("devices will be grabbed back once every key is released.");
+        }
+    }
+
+    /// Ask to resume if the auto-resume deadline has passed.
+    pub fn tick(&mut self, now: Instant) {
+        if self.until.is_some_and(|e| now >= e) {
+            self.resume();
+        }
+    }
+
+    /// Returns true if devices must be grabbed back.
+    pub fn resumed(&mut self, keys_held: bool) -> bool {
+        if self.resuming && !keys_held {
+            *self = Self::default();
+            info!("devices grabbed back, resuming.");
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_after_timeout_once_keys_released() {
+        let mut pause = Pause::default();
+        let start = Instant::now();
+        assert!(pause.pause(Some(Duration::from_secs(60)), start));
+        assert!(!pause.pause(Some(Duration::from_secs(60)), start));
+
+        pause.tick(start + Duration::from_secs(30));
+        assert!(!pause.resuming);
+        pause.tick(start + Duration::from_secs(60));
+        assert!(pause.resuming);
+
+        // Keys still held on devices.
+        assert!(!pause.resumed(true));
+        assert!(pause.paused);
+        assert!(pause.resumed(false));
+        assert!(!pause.paused);
+    }
+}
@@ -0,0 +1,448 @@
+/*
+* Input pipeline.
+*
+* Everything that happens to an input event between its source (grabbed devices, recordings)
+* and its sinks (virtual devices, fake sinks):
+* device state, escape chord, pause, bind matching and forwarding.
+*
+*/
+
+use super::devices::{DeviceKind, Devices, GrabbedDevice};
+use super::escape::Escape;
+use super::pause::Pause;
+use super::pointer;
+use super::sink::{EventSink, Sinks};
+use super::source::EventSource;
+use super::switch;
+use super::utils::{self, KeyState, KeyboardState, SubmapState};
+
+use crate::config::{Config, Switches};
+use crate::executor::Executor;
+
+use evdev::{EventSummary, InputEvent, SynchronizationCode};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Error
+use crate::error::MudrasError;
+use miette::Result;
+use tracing::{error, info, warn};
+
+#[derive(Debug)]
+pub struct Pipeline {
+    pub devices: Devices,
+    /// Per device State
+    pub keyboard_states: HashMap<String, KeyboardState>,
+    /// Pointer events waiting for the end of their frame.
+    pointer_frames: HashMap<String, Vec<InputEvent>>,
+    /// Bindings state
+    pub submaps_state: SubmapState,
+    switches: Switches,
+    // Emergency escape and stuck keys watchdog
+    escape: Escape,
+    stuck_key_timeout: Option<Duration>,
+    pub pause: Pause,
+    pause_timeout: Option<Duration>,
+}
+
+impl Pipeline {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            devices: Devices::new(config),
+            keyboard_states: HashMap::new(),
+            pointer_frames: HashMap::new(),
+            submaps_state: SubmapState {
+                submaps: config.submaps.clone(),
+                ..Default::default()
+            },
+            switches: config.switches.clone(),
+            escape: Escape::new(&config.options),
+            stuck_key_timeout: config.options.stuck_key_timeout,
+            pause: Pause::default(),
+            pause_timeout: config.options.pause_timeout,
+        }
+    }
+
+    /// Apply a new configuration.
+    /// Devices must be registered again by the caller.
+    pub fn configure(&mut self, config: &Config) {
+        if !config.submaps.contains_key(&self.submaps_state.current) {
+            self.submaps_state.current = "main".to_owned();
+        }
+        self.submaps_state.submaps = config.submaps.clone();
+        self.switches = config.switches.clone();
+        self.escape.set_options(&config.options);
+        self.stuck_key_timeout = config.options.stuck_key_timeout;
+        self.pause_timeout = config.options.pause_timeout;
+        self.devices.configure(config);
+    }
+
+    /// Forget about an unplugged or released device.
+    /// Keys it still holds are released so that they don't get stuck.
+    pub fn remove_device<T: EventSink>(
+        &mut self,
+        path: &str,
+        device: &GrabbedDevice,
+        sinks: &mut Sinks<T>,
+    ) {
+        self.pointer_frames.remove(path);
+        let Some(state) = self.keyboard_states.remove(path) else {
+            return;
+        };
+        let events = state.release_events();
+        if events.is_empty() {
+            return;
+        }
+        let sink = match device.kind {
+            DeviceKind::Keyboard => &mut sinks.keyboard,
+            DeviceKind::Pointer => &mut sinks.pointer,
+            DeviceKind::Switch => return,
+        };
+        if let Err(e) = sink.emit(&events) {
+            error!("couldn't release keys of device at '{}': {}", path, e);
+        }
+    }
+
+    /// Release every key still pressed on the virtual devices.
+    /// Keys forwarded as pressed would stay pressed for the compositor otherwise.
+    pub fn release_keys<T: EventSink>(&self, sinks: &mut Sinks<T>) {
+        for (path, state) in &self.keyboard_states {
+            let events = state.release_events();
+            if events.is_empty() {
+                continue;
+            }
+            let res = match self.devices.kind(path) {
+                Some(DeviceKind::Keyboard) => sinks.keyboard.emit(&events),
+                Some(DeviceKind::Pointer) => sinks.pointer.emit(&events),
+                _ => Ok(()),
+            };
+            if let Err(e) = res {
+                error!("couldn't release keys of device at '{}': {}", path, e);
+            }
+        }
+    }
+
+    /// Release devices and stop matching, until resumed or timed out.
+    pub fn pause<S: EventSource, T: EventSink>(
+        &mut self,
+        timeout: Option<Duration>,
+        source: &mut S,
+        sinks: &mut Sinks<T>,
+    ) {
+        if self
+            .pause
+            .pause(timeout.or(self.pause_timeout), Instant::now())
+        {
+            self.release_keys(sinks);
+            source.set_grab(&self.devices, false);
+        }
+    }
+
+    /// Release devices and pause, or resume once every key is released.
+    fn toggle_escape<S: EventSource, T: EventSink>(
+        &mut self,
+        source: &mut S,
+        sinks: &mut Sinks<T>,
+    ) {
+        if self.pause.paused {
+            info!("escape chord held.");
+            self.pause.resume();
+            return;
+        }
+        warn!("escape chord held, releasing devices.");
+        if self.pause.pause(None, Instant::now()) {
+            self.release_keys(sinks);
+            source.set_grab(&self.devices, false);
+        }
+    }
+
+    /// Periodic checks: escape chord, pause timeout, stuck keys.
+    pub fn tick<S: EventSource, T: EventSink>(
+        &mut self,
+        now: Instant,
+        source: &mut S,
+        sinks: &mut Sinks<T>,
+    ) -> Result<(), MudrasError> {
+        if self.escape.fire(now) {
+            self.toggle_escape(source, sinks);
+        }
+        self.pause.tick(now);
+        // Pointers are not followed while paused, only keyboards are waited for.
+        let held = self.keyboard_states.iter().any(|(path, state)| {
+            self.devices.kind(path) == Some(DeviceKind::Keyboard) && state.is_held()
+        });
+        if self.pause.resumed(held) {
+            source.set_grab(&self.devices, true);
+        }
+        if let (false, Some(timeout)) = (self.pause.paused, self.stuck_key_timeout) {
+            for (path, state) in self.keyboard_states.iter_mut() {
+                if self.devices.kind(path) != Some(DeviceKind::Keyboard) {
+                    continue;
+                }
+                let events = state.release_stuck(timeout, now);
+                if !events.is_empty() {
+                    warn!(
+                        "released {} stuck key(s) of device at '{}'.",
+                        events.len(),
+                        path
+                    );
+                    sinks.keyboard.emit(&events)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Process an event of the device at path.
+    pub fn handle<S: EventSource, T: EventSink>(
+        &mut self,
+        path: &str,
+        event: InputEvent,
+        executor: &mut Executor,
+        source: &mut S,
+        sinks: &mut Sinks<T>,
+    ) -> Result<(), MudrasError> {
+        let kind = self.devices.kind(path);
+        if self.pause.paused && kind != Some(DeviceKind::Keyboard) {
+            return Ok(());
+        }
+        if kind == Some(DeviceKind::Pointer) {
+            // Forward pointer frames once complete.
+            let frame = self.pointer_frames.entry(path.to_owned()).or_default();
+            if let EventSummary::Synchronization(_, SynchronizationCode::SYN_REPORT, _) =
+                event.destructure()
+            {
+                let frame = std::mem::take(frame);
+                let forward = pointer::handle_frame(
+                    frame,
+                    path,
+                    &self.devices,
+                    &mut self.keyboard_states,
+                    &mut self.submaps_state,
+                    executor,
+                )?;
+                if !forward.is_empty() {
+                    sinks.pointer.emit(&forward)?;
+                }
+            } else {
+                frame.push(event);
+            }
+            return Ok(());
+        }
+        if let (false, EventSummary::Switch(_, code, value)) =
+            (self.pause.paused, event.destructure())
+        {
+            switch::trigger_switch(
+                &self.switches,
+                code,
+                value,
+                self.devices.scope(path),
+                &mut self.submaps_state,
+                executor,
+            )?;
+        }
+        if kind == Some(DeviceKind::Switch) {
+            // Not grabbed, nothing to forward.
+            return Ok(());
+        }
+        match event.destructure() {
+            EventSummary::Key(_type, keycode, value) => {
+                let key_state = match value {
+                    1 => KeyState::Pressed,
+                    0 => KeyState::Released,
+                    _ => KeyState::Undefined,
+                };
+                match key_state {
+                    KeyState::Pressed | KeyState::Released => {
+                        // trace!("key={:#?},state={:#?}", keycode, state);
+
+                        // Update keyboard representation state.
+                        self.keyboard_states
+                            .entry(path.to_owned())
+                            .or_default()
+                            .update(keycode, &key_state);
+                        // Keys of the whole seat are matched against binds.
+                        let keyboard_state = &self.devices.seat_state(path, &self.keyboard_states);
+
+                        // Escape chord comes before any bind.
+                        let now = Instant::now();
+                        self.escape.update(keyboard_state, now);
+                        if self.escape.fire(now) {
+                            self.toggle_escape(source, sinks);
+                        }
+                        if self.pause.paused {
+                            return Ok(());
+                        }
+
+                        // Trigger action.
+                        let fate = utils::trigger_action(
+                            &mut self.submaps_state,
+                            keyboard_state,
+                            keycode,
+                            &key_state,
+                            self.devices.scope(path),
+                            executor,
+                        )?;
+                        let state = self.keyboard_states.entry(path.to_owned()).or_default();
+                        // Held keys that won't complete a bind go first.
+                        let replay = state.replay(fate);
+                        if !replay.is_empty() {
+                            sinks.keyboard.emit(&replay)?;
+                        }
+                        if state.forward(keycode, &key_state, fate) {
+                            sinks.keyboard.emit(&[event])?;
+                        }
+                    }
+                    _ => {
+                        // Repeats prove the key is still held.
+                        self.keyboard_states
+                            .entry(path.to_owned())
+                            .or_default()
+                            .touch(keycode);
+                    }
+                }
+            }
+            _ if self.pause.paused => {}
+            EventSummary::Switch(_, _, _) => {
+                sinks.switch.emit(&[event])?;
+            }
+            _ => {
+                sinks.keyboard.emit(&[event])?;
+            }
+        };
+        Ok(())
+    }
+}
+
+/// Feed every event of a source through the pipeline.
+pub async fn replay<S: EventSource, T: EventSink>(
+    pipeline: &mut Pipeline,
+    executor: &mut Executor,
+    source: &mut S,
+    sinks: &mut Sinks<T>,
+) -> Result<(), MudrasError> {
+    while let Some((path, event)) = source.next_event().await {
+        pipeline.handle(&path, event, executor, source, sinks)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::record::{self, Record};
+    use crate::input::simulate;
+    use crate::input::sink::FakeSink;
+    use crate::input::source::ReplaySource;
+    use evdev::{EventType, KeyCode};
+    use pretty_assertions::assert_eq;
+
+    /// Replay a recording against a configuration,
+    /// returns the events forwarded to the virtual keyboard.
+    async fn replay_recording(kdl: &str, recording: &str) -> Result<Vec<InputEvent>> {
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let records: Vec<Record> = record::parse(recording)?;
+        let mut pipeline = Pipeline::new(&config);
+        record::register(&mut pipeline.devices, &records);
+        let mut executor = Executor::default();
+        let mut source = ReplaySource::new(&records);
+        let mut sinks: Sinks<FakeSink> = Sinks::default();
+        replay(&mut pipeline, &mut executor, &mut source, &mut sinks).await?;
+        Ok(sinks.keyboard.events)
+    }
+
+    /// Type steps (ex: "a down, a up") on a single keyboard,
+    /// returns the events forwarded to the virtual keyboard.
+    fn type_steps(kdl: &str, steps: &str) -> Result<Vec<InputEvent>> {
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let mut pipeline = Pipeline::new(&config);
+        pipeline
+            .devices
+            .insert("keyboard", DeviceKind::Keyboard, None);
+        let mut executor = Executor::dry_run();
+        let mut source = ReplaySource::default();
+        let mut sinks: Sinks<FakeSink> = Sinks::default();
+        for (key, state) in simulate::parse_steps(steps)? {
+            let value = match state {
+                KeyState::Pressed => 1,
+                _ => 0,
+            };
+            let event = InputEvent::new(EventType::KEY.0, key.code(), value);
+            pipeline.handle("keyboard", event, &mut executor, &mut source, &mut sinks)?;
+        }
+        Ok(sinks.keyboard.events)
+    }
+
+    #[test]
+    fn forward_keys_off_the_way_to_binds() -> Result<()> {
+        let kdl = r#"
+            brightness_up {
+                @press {
+                    - "light -A 10"
+                }
+            }
+            f+j {
+                @press {
+                    - "notify-send fj"
+                }
+            }
+            Super+n swallow=false {
+                @press {
+                    - "notify-send hi"
+                }
+            }
+        "#;
+        let key = |code: KeyCode, value| InputEvent::new(EventType::KEY.0, code.code(), value);
+        let a = vec![key(KeyCode::KEY_A, 1), key(KeyCode::KEY_A, 0)];
+
+        // Unbound keys are forwarded once.
+        assert_eq!(a, type_steps(kdl, "a down, a up")?);
+        // A single key bind isn't a modifier.
+        assert_eq!(
+            a,
+            type_steps(kdl, "brightness_up down, a down, a up, brightness_up up")?
+        );
+        // A key on its way to a bind is held, and swallowed with its release by the bind.
+        assert_eq!(
+            Vec::<InputEvent>::new(),
+            type_steps(kdl, "f down, j down, j up, f up")?
+        );
+        // It is replayed when the keys diverge from the bind, or when it is released.
+        let f = |value| key(KeyCode::KEY_F, value);
+        let x = |value| key(KeyCode::KEY_X, value);
+        assert_eq!(
+            vec![f(1), x(1), x(0), f(0)],
+            type_steps(kdl, "f down, x down, x up, f up")?
+        );
+        assert_eq!(vec![f(1), f(0)], type_steps(kdl, "f down, f up")?);
+        // Modifiers are forwarded, and triggers of binds that don't swallow.
+        assert_eq!(
+            vec![
+                key(KeyCode::KEY_LEFTMETA, 1),
+                key(KeyCode::KEY_N, 1),
+                key(KeyCode::KEY_N, 0),
+                key(KeyCode::KEY_LEFTMETA, 0)
+            ],
+            type_steps(kdl, "Super_L down, n down, n up, Super_L up")?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn swallow_bind_typed_across_devices() -> Result<()> {
+        let kdl = r#"
+            Super+Enter {
+                @press {
+                    @exit
+                }
+            }
+        "#;
+        let events = replay_recording(kdl, include_str!("recordings/super_enter.jsonl")).await?;
+        let key = |code: KeyCode, value| InputEvent::new(EventType::KEY.0, code.code(), value);
+        // Enter press is swallowed, Super is passed.
+        assert!(events.contains(&key(KeyCode::KEY_LEFTMETA, 1)));
+        assert!(events.contains(&key(KeyCode::KEY_LEFTMETA, 0)));
+        assert!(!events.contains(&key(KeyCode::KEY_ENTER, 1)));
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+/*
+* Pointer devices.
+*
+* Mouse buttons are plain key codes and are matched like keyboard keys.
+* Wheel notches have no key code, so they are mapped to pseudo keys
+* outside of the evdev key range, that are pressed and released at once.
+*
+* Pointer events are buffered until the end of their frame (SYN_REPORT),
+* so that swallowed events don't leave half frames behind.
+*
+*/
+
+use super::devices::Devices;
+use super::utils::{self, Fate, KeyState, KeyboardState, SubmapState};
+use crate::executor::Executor;
+
+use evdev::{EventSummary, InputEvent, KeyCode, RelativeAxisCode};
+use std::collections::HashMap;
+
+// Error
+use crate::error::MudrasError;
+use miette::Result;
+
+pub const WHEEL_UP: KeyCode = KeyCode::new(0x300);
+pub const WHEEL_DOWN: KeyCode = KeyCode::new(0x301);
+pub const WHEEL_LEFT: KeyCode = KeyCode::new(0x302);
+pub const WHEEL_RIGHT: KeyCode = KeyCode::new(0x303);
+
+/// Convert a relative wheel motion into its pseudo key.
+pub fn wheel_to_key(axis: RelativeAxisCode, value: i32) -> Option<KeyCode> {
+    match axis {
+        RelativeAxisCode::REL_WHEEL if value > 0 => Some(WHEEL_UP),
+        RelativeAxisCode::REL_WHEEL if value < 0 => Some(WHEEL_DOWN),
+        RelativeAxisCode::REL_HWHEEL if value < 0 => Some(WHEEL_LEFT),
+        RelativeAxisCode::REL_HWHEEL if value > 0 => Some(WHEEL_RIGHT),
+        _ => None,
+    }
+}
+
+fn is_hires_wheel(axis: RelativeAxisCode) -> bool {
+    axis == RelativeAxisCode::REL_WHEEL_HI_RES || axis == RelativeAxisCode::REL_HWHEEL_HI_RES
+}
+
+/// Match the buttons and wheel events of a pointer frame against binds.
+/// Keys held on the other devices of the seat take part in the match.
+/// Returns the events to forward.
+pub fn handle_frame(
+    frame: Vec<InputEvent>,
+    path: &str,
+    devices: &Devices,
+    keyboard_states: &mut HashMap<String, KeyboardState>,
+    submaps_state: &mut SubmapState,
+    executor: &mut Executor,
+) -> Result<Vec<InputEvent>, MudrasError> {
+    let device = devices.scope(path);
+    let mut forward = vec![];
+    // High resolution wheel events follow the fate of the low resolution ones.
+    let mut hires = vec![];
+    let mut swallow_wheel = false;
+
+    for event in frame {
+        match event.destructure() {
+            EventSummary::Key(_, keycode, value @ (0 | 1)) => {
+                let key_state = match value {
+                    1 => KeyState::Pressed,
+                    _ => KeyState::Released,
+                };
+                let state = seat_state(devices, keyboard_states, path, keycode, &key_state);
+                let fate = utils::trigger_action(
+                    submaps_state,
+                    &state,
+                    keycode,
+                    &key_state,
+                    device,
+                    executor,
+                )?;
+                let state = keyboard_states.entry(path.to_owned()).or_default();
+                forward.extend(state.replay(fate));
+                if state.forward(keycode, &key_state, fate) {
+                    forward.push(event);
+                }
+            }
+            EventSummary::RelativeAxis(_, axis, value) => {
+                if let Some(keycode) = wheel_to_key(axis, value) {
+                    let state =
+                        seat_state(devices, keyboard_states, path, keycode, &KeyState::Pressed);
+                    // Wheel notches are released right away.
+                    if let Some(e) = keyboard_states.get_mut(path) {
+                        e.forget(keycode);
+                    }
+                    if utils::trigger_action(
+                        submaps_state,
+                        &state,
+                        keycode,
+                        &KeyState::Pressed,
+                        device,
+                        executor,
+                    )? == Fate::Forward
+                    {
+                        forward.push(event);
+                    } else {
+                        swallow_wheel = true;
+                    }
+                } else if is_hires_wheel(axis) {
+                    hires.push(event);
+                } else {
+                    forward.push(event);
+                }
+            }
+            _ => forward.push(event),
+        }
+    }
+    if !swallow_wheel {
+        forward.extend(hires);
+    }
+    Ok(forward)
+}
+
+/// Update the device state and merge it with its seat mates ones.
+fn seat_state(
+    devices: &Devices,
+    keyboard_states: &mut HashMap<String, KeyboardState>,
+    path: &str,
+    keycode: KeyCode,
+    key_state: &KeyState,
+) -> KeyboardState {
+    keyboard_states
+        .entry(path.to_owned())
+        .or_default()
+        .update(keycode, key_state);
+    devices.seat_state(path, keyboard_states)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::input::devices::{DeviceKind, GrabbedDevice};
+    use evdev::EventType;
+    use miette::Result;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn swallow_bound_wheel_with_keyboard_modifier() -> Result<()> {
+        let config = Config::from_kdl(
+            "test.kdl",
+            r#"
+            Super+WheelUp {
+                @press {
+                    @exit
+                }
+            }
+            "#,
+        )?;
+        let mut submaps_state = SubmapState {
+            submaps: config.submaps,
+            ..Default::default()
+        };
+        let mut executor = Executor::default();
+        let mut devices = Devices::default();
+        for (path, kind) in [
+            ("keyboard", DeviceKind::Keyboard),
+            ("mouse", DeviceKind::Pointer),
+        ] {
+            let device = GrabbedDevice {
+                kind,
+                scope: None,
+                seat: Some("default".to_owned()),
+            };
+            devices.grabbed.insert(path.to_owned(), device);
+        }
+        let mut keyboard_states: HashMap<String, KeyboardState> = HashMap::new();
+        keyboard_states
+            .entry("keyboard".to_owned())
+            .or_default()
+            .update(KeyCode::KEY_LEFTMETA, &KeyState::Pressed);
+
+        let motion = InputEvent::new(EventType::RELATIVE.0, RelativeAxisCode::REL_X.0, 3);
+        let wheel = InputEvent::new(EventType::RELATIVE.0, RelativeAxisCode::REL_WHEEL.0, 1);
+        let hires = InputEvent::new(
+            EventType::RELATIVE.0,
+            RelativeAxisCode::REL_WHEEL_HI_RES.0,
+            120,
+        );
+
+        // Bound wheel with the modifier held on the keyboard.
+        let res = handle_frame(
+            vec![motion, wheel, hires],
+            "mouse",
+            &devices,
+            &mut keyboard_states,
+            &mut submaps_state,
+            &mut executor,
+        )?;
+        assert_eq!(vec![motion], res);
+
+        // Unbound wheel direction is forwarded unchanged.
+        let wheel = InputEvent::new(EventType::RELATIVE.0, RelativeAxisCode::REL_WHEEL.0, -1);
+        let res = handle_frame(
+            vec![wheel],
+            "mouse",
+            &devices,
+            &mut keyboard_states,
+            &mut submaps_state,
+            &mut executor,
+        )?;
+        assert_eq!(vec![wheel], res);
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+/*
+* Input recordings.
+*
+* A recording is a json lines file:
+* every device is described once, before the timestamped events it emitted.
+*
+* {"device":{"path":"/dev/input/event3","name":"AT keyboard","kind":"keyboard"}}
+* {"event":{"path":"/dev/input/event3","time":0.0,"type":1,"code":125,"value":1}}
+*
+*/
+
+use super::devices::{DeviceKind, Devices};
+use super::utils;
+
+use evdev::{Device, EventStream, InputEvent};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+use tokio_stream::{StreamExt, StreamMap};
+
+// Error
+use crate::error::{LibError, MudrasError};
+use miette::Result;
+use tracing::info;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Record {
+    /// A recorded device.
+    Device {
+        path: String,
+        name: String,
+        kind: DeviceKind,
+    },
+    /// An input event, timestamped in seconds from the start of the recording.
+    Event {
+        path: String,
+        time: f64,
+        #[serde(rename = "type")]
+        kind: u16,
+        code: u16,
+        value: i32,
+    },
+}
+
+impl Record {
+    pub fn event(path: &str, time: f64, event: &InputEvent) -> Self {
+        Record::Event {
+            path: path.to_owned(),
+            time,
+            kind: event.event_type().0,
+            code: event.code(),
+            value: event.value(),
+        }
+    }
+}
+
+/// Parse a recording.
+pub fn parse(string: &str) -> Result<Vec<Record>, MudrasError> {
+    let mut records = vec![];
+    for line in string.lines().filter(|e| !e.trim().is_empty()) {
+        records.push(serde_json::from_str(line)?);
+    }
+    Ok(records)
+}
+
+/// Track the devices of a recording as if they were grabbed.
+pub fn register(devices: &mut Devices, records: &[Record]) {
+    for record in records {
+        if let Record::Device { path, kind, .. } = record {
+            devices.insert(path, *kind, None);
+        }
+    }
+}
+
+/// Open the given devices, every keyboard and pointer by default.
+/// Devices are not grabbed.
+pub fn open_devices(paths: &[String]) -> Result<Vec<(String, Device)>, MudrasError> {
+    let mut opened: Vec<(String, Device)> = vec![];
+    if paths.is_empty() {
+        for (path, device) in evdev::enumerate() {
+            if utils::check_device_is_keyboard(&device) || utils::check_device_is_pointer(&device) {
+                opened.push((path.to_string_lossy().to_string(), device));
+            }
+        }
+    } else {
+        for path in paths {
+            opened.push((path.to_owned(), Device::open(path)?));
+        }
+    }
+    if opened.is_empty() {
+        let message = "No device to listen to.";
+        let help = "Give device paths with --device (ex: /dev/input/event3).";
+        return Err(LibError::builder().msg(message).help(help).build().into());
+    }
+    Ok(opened)
+}
+
+/// Dump the events of the given devices (every keyboard and pointer by default)
+/// to a file, until interrupted.
+/// Devices are not grabbed.
+pub async fn record(file: &Path, paths: &[String]) -> Result<(), MudrasError> {
+    let opened = open_devices(paths)?;
+    let mut out = tokio::fs::File::create(file).await?;
+    let mut streams: StreamMap<String, EventStream> = StreamMap::new();
+    for (path, device) in opened {
+        let kind = if utils::check_device_is_pointer(&device) {
+            DeviceKind::Pointer
+        } else if utils::check_device_is_keyboard(&device) {
+            DeviceKind::Keyboard
+        } else {
+            DeviceKind::Switch
+        };
+        let name = device.name().unwrap_or("[unknown]").to_owned();
+        info!("recording device '{}' at '{}'.", name, path);
+        write(
+            &mut out,
+            &Record::Device {
+                path: path.clone(),
+                name,
+                kind,
+            },
+        )
+        .await?;
+        streams.insert(path, device.into_event_stream()?);
+    }
+
+    let start = Instant::now();
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            Some((path, Ok(event))) = streams.next() => {
+                let time = start.elapsed().as_secs_f64();
+                write(&mut out, &Record::event(&path, time, &event)).await?;
+            }
+        }
+    }
+    out.flush().await?;
+    info!("recording saved to {:?}.", file);
+    Ok(())
+}
+
+async fn write(out: &mut tokio::fs::File, record: &Record) -> Result<(), MudrasError> {
+    let mut json = serde_json::to_string(record)?;
+    json.push('\n');
+    out.write_all(json.as_bytes()).await?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+/*
+* Dry run of a key sequence against a configuration.
+*
+* Keys go through the regular pipeline, from a fake keyboard to a fake sink,
+* with an executor that records triggers instead of spawning commands.
+*
+* "Super down, n down, n up, Super up"
+*
+*/
+
+use super::devices::DeviceKind;
+use super::modifier;
+use super::pipeline::Pipeline;
+use super::sink::{FakeSink, Sinks};
+use super::source::ReplaySource;
+use super::utils::KeyState;
+
+use crate::config::utils::{key_from_name, name_from_key};
+use crate::config::Config;
+use crate::executor::{Executor, Trigger};
+
+use evdev::{EventType, InputEvent, KeyCode};
+use std::fmt;
+
+// Error
+use crate::error::{LibError, MudrasError};
+use miette::Result;
+
+const DEVICE: &str = "simulated keyboard";
+
+/// What happened to a simulated key event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub key: KeyCode,
+    pub state: KeyState,
+    /// Whether the event reached the virtual keyboard.
+    pub forwarded: bool,
+    pub triggers: Vec<Trigger>,
+    /// Submap before and after the event, if it changed.
+    pub transition: Option<(String, String)>,
+}
+
+/// Parse a comma separated list of "<key> down|up".
+pub fn parse_steps(string: &str) -> Result<Vec<(KeyCode, KeyState)>, MudrasError> {
+    let mut steps = vec![];
+    for step in string
+        .split(',')
+        .map(|e| e.trim())
+        .filter(|e| !e.is_empty())
+    {
+        let help = "Write steps as \"<key> down\" or \"<key> up\" (ex: \"Super down, n down\").";
+        let err = |msg: String| LibError::builder().msg(&msg).help(help).build();
+        let (name, state) = step
+            .rsplit_once(' ')
+            .ok_or_else(|| err(format!("Missing key state in step {:?}.", step)))?;
+        let state = match state {
+            "down" | "press" => KeyState::Pressed,
+            "up" | "release" => KeyState::Released,
+            _ => return Err(err(format!("Unknown key state {:?}.", state)).into()),
+        };
+        // Modifiers of either side are pressed on the left.
+        let key = key_from_name(name.trim())
+            .map(modifier::physical)
+            .ok_or_else(|| err(format!("Unknown key {:?}.", name.trim())))?;
+        steps.push((key, state));
+    }
+    Ok(steps)
+}
+
+/// Feed the steps to the configuration matcher, one at a time.
+pub fn simulate(config: &Config, steps: &[(KeyCode, KeyState)]) -> Result<Vec<Step>, MudrasError> {
+    let mut pipeline = Pipeline::new(config);
+    pipeline.devices.insert(DEVICE, DeviceKind::Keyboard, None);
+    let mut executor = Executor::dry_run();
+    executor.set_options(&config.options);
+    let mut source = ReplaySource::default();
+    let mut sinks: Sinks<FakeSink> = Sinks::default();
+
+    let mut res = vec![];
+    for (key, state) in steps {
+        let value = match state {
+            KeyState::Pressed => 1,
+            _ => 0,
+        };
+        let event = InputEvent::new(EventType::KEY.0, key.code(), value);
+        let before = pipeline.submaps_state.current.clone();
+        pipeline.handle(DEVICE, event, &mut executor, &mut source, &mut sinks)?;
+        let after = pipeline.submaps_state.current.clone();
+        res.push(Step {
+            key: *key,
+            state: state.to_owned(),
+            forwarded: !std::mem::take(&mut sinks.keyboard.events).is_empty(),
+            triggers: executor.take_triggers(),
+            transition: (before != after).then_some((before, after)),
+        });
+    }
+    Ok(res)
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            KeyState::Pressed => "down",
+            _ => "up",
+        };
+        let fate = match self.forwarded {
+            true => "forwarded",
+            false => "swallowed",
+        };
+        writeln!(f, "{} {}: {}", name_from_key(self.key), state, fate)?;
+        for trigger in &self.triggers {
+            write!(f, "{}", trigger)?;
+        }
+        if let Some((before, after)) = &self.transition {
+            writeln!(f, "  submap {:?} -> {:?}", before, after)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Command, Keyword};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn simulate_submap_enter() -> Result<()> {
+        let kdl = r#"
+            Super+N {
+                @press {
+                    @enter "nav"
+                    - "notify-send nav"
+                }
+            }
+            @submap name="nav" {
+                Escape {
+                    @press {
+                        @exit
+                    }
+                }
+            }
+        "#;
+        let config = Config::from_kdl("test.kdl", kdl)?;
+        let steps = parse_steps("Super down, n down, n up, Super up, Escape down")?;
+        let res = simulate(&config, &steps)?;
+
+        assert!(res[0].triggers.is_empty());
+        assert_eq!(1, res[1].triggers.len());
+        assert_eq!(
+            vec![
+                Command::Internal(Keyword::Enter("nav".to_owned())),
+                Command::Sh("notify-send nav".to_owned())
+            ],
+            res[1].triggers[0].commands
+        );
+        assert!(!res[1].forwarded);
+        assert_eq!(
+            Some(("main".to_owned(), "nav".to_owned())),
+            res[1].transition
+        );
+        assert_eq!(
+            Some(("nav".to_owned(), "main".to_owned())),
+            res[4].transition
+        );
+
+        assert!(parse_steps("Super").is_err());
+        assert!(parse_steps("Hyperdrive down").is_err());
+        Ok(())
+    }
+}
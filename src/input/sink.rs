@@ -0,0 +1,49 @@
+/*
+* Event sinks.
+*
+* Forwarded events end up on virtual devices,
+* or in a fake sink that only records them (tests, replays).
+*
+*/
+
+use evdev::{uinput::VirtualDevice, InputEvent};
+
+// Error
+use crate::error::MudrasError;
+use miette::Result;
+
+pub trait EventSink {
+    /// Emit events as a single frame (followed by a SYN_REPORT).
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), MudrasError>;
+}
+
+impl EventSink for VirtualDevice {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), MudrasError> {
+        VirtualDevice::emit(self, events)?;
+        Ok(())
+    }
+}
+
+/// Keeps emitted events (without the trailing SYN_REPORT).
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct FakeSink {
+    pub events: Vec<InputEvent>,
+}
+
+impl EventSink for FakeSink {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), MudrasError> {
+        self.events.extend_from_slice(events);
+        Ok(())
+    }
+}
+
+/// Apparently, having a single uinput device with keys, relative axes and switches
+/// prevents some libraries to listen to these events. The easy fix is to have separate
+/// virtual devices, one for keys, one for pointer buttons and relative axes,
+/// and another one just for switches.
+#[derive(Default, Debug)]
+pub struct Sinks<T: EventSink> {
+    pub keyboard: T,
+    pub pointer: T,
+    pub switch: T,
+}
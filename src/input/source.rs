@@ -0,0 +1,87 @@
+/*
+* Event sources.
+*
+* Events come from grabbed devices,
+* or from a recording replayed through the very same pipeline.
+*
+*/
+
+use super::devices::{DeviceKind, Devices};
+use super::record::Record;
+
+use evdev::{EventStream, InputEvent};
+use std::collections::VecDeque;
+use std::future::Future;
+use tokio_stream::{StreamExt, StreamMap};
+
+use tracing::error;
+
+pub trait EventSource {
+    /// Next event, with the path of the device it comes from.
+    fn next_event(&mut self) -> impl Future<Output = Option<(String, InputEvent)>>;
+    /// Grab or release every grabbed device.
+    fn set_grab(&mut self, devices: &Devices, grab: bool);
+}
+
+/// Event streams of the grabbed devices, by path.
+#[derive(Default)]
+pub struct DeviceSource {
+    pub streams: StreamMap<String, EventStream>,
+}
+
+impl EventSource for DeviceSource {
+    async fn next_event(&mut self) -> Option<(String, InputEvent)> {
+        loop {
+            match self.streams.next().await? {
+                (path, Ok(event)) => return Some((path, event)),
+                (path, Err(e)) => error!("couldn't read device at '{}': {}", path, e),
+            }
+        }
+    }
+    fn set_grab(&mut self, devices: &Devices, grab: bool) {
+        for (path, stream) in self.streams.iter_mut() {
+            if devices.kind(path) == Some(DeviceKind::Switch) {
+                continue;
+            }
+            let res = match grab {
+                true => stream.device_mut().grab(),
+                false => stream.device_mut().ungrab(),
+            };
+            if let Err(e) = res {
+                error!("couldn't change grab of device at '{}': {}", path, e);
+            }
+        }
+    }
+}
+
+/// Events of a recording, replayed without delay.
+#[derive(Default, Debug)]
+pub struct ReplaySource {
+    events: VecDeque<(String, InputEvent)>,
+}
+
+impl ReplaySource {
+    pub fn new(records: &[Record]) -> Self {
+        let events = records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Event {
+                    path,
+                    kind,
+                    code,
+                    value,
+                    ..
+                } => Some((path.to_owned(), InputEvent::new(*kind, *code, *value))),
+                _ => None,
+            })
+            .collect();
+        Self { events }
+    }
+}
+
+impl EventSource for ReplaySource {
+    async fn next_event(&mut self) -> Option<(String, InputEvent)> {
+        self.events.pop_front()
+    }
+    fn set_grab(&mut self, _devices: &Devices, _grab: bool) {}
+}
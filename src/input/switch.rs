@@ -0,0 +1,71 @@
+/*
+* Switch events (lid, tablet mode, jacks...).
+*
+* Switches are bound by name with the @switch node,
+* switching on runs the @on commands and switching off the @off ones.
+*
+* Switch-only devices are listened to but never grabbed,
+* so that logind and the compositor still get lid and tablet mode changes.
+*
+*/
+
+use super::utils::{self, KeyState, SubmapState};
+use super::virtuals::get_all_switches;
+use crate::config::Switches;
+use crate::executor::{BindId, Executor};
+
+use evdev::{KeyCode, SwitchCode};
+
+// Error
+use crate::error::MudrasError;
+use miette::Result;
+use tracing::debug;
+
+/// Submap name of switch binds identities.
+pub const SWITCH_SUBMAP: &str = "@switch";
+/// Switch pseudo keys start after the wheel ones.
+const SWITCH_KEYS: u16 = 0x320;
+
+/// Pseudo key identifying a switch in bind identities.
+pub fn switch_to_key(switch: SwitchCode) -> KeyCode {
+    KeyCode::new(SWITCH_KEYS + switch.0)
+}
+
+pub fn key_to_switch(key: KeyCode) -> Option<SwitchCode> {
+    let code = key.code().checked_sub(SWITCH_KEYS)?;
+    get_all_switches().iter().find(|e| e.0 == code).copied()
+}
+
+/// Run the commands bound to a switch state change.
+pub fn trigger_switch(
+    switches: &Switches,
+    switch: SwitchCode,
+    value: i32,
+    device: Option<&str>,
+    submaps_state: &mut SubmapState,
+    executor: &mut Executor,
+) -> Result<(), MudrasError> {
+    let Some(bind) = switches.get(&switch) else {
+        return Ok(());
+    };
+    let (key_state, args) = match value {
+        0 => (KeyState::Released, &bind.off),
+        _ => (KeyState::Pressed, &bind.on),
+    };
+    let Some(args) = args else {
+        return Ok(());
+    };
+    if args.device.is_some() && args.device.as_deref() != device {
+        return Ok(());
+    }
+    debug!("switch {:#?} turned {:?}.", bind.name, key_state);
+    let id = BindId {
+        submap: SWITCH_SUBMAP.to_owned(),
+        device: args.device.clone(),
+        sequence: vec![(switch_to_key(switch), key_state)],
+    };
+    if executor.run(&id, args)? {
+        utils::apply_keywords(&mut submaps_state.current, args);
+    }
+    Ok(())
+}
@@ -1,21 +1,24 @@
-use super::utils;
+use super::devices::{DeviceKind, Devices, GrabbedDevice};
 
 // Keyboard
-use evdev::{Device, EventStream, EventSummary, KeyCode};
-use std::collections::HashMap;
+use evdev::{Device, EventStream};
 
-use tokio_stream::{StreamExt, StreamMap};
-use tokio_udev::{AsyncMonitorSocket, Event as UdevEvent, EventType, MonitorBuilder};
+use tokio_stream::StreamMap;
+use tokio_udev::{Event as UdevEvent, EventType};
 
 // Error
-use crate::error::{LibError, MudrasError, WrapError};
-use miette::{Error, Result};
-use tracing::{debug, error, info, trace, warn};
+use crate::error::MudrasError;
+use miette::Result;
+use tracing::{info, trace, warn};
 
+/// Returns the device that has been unplugged, if any.
+/// New devices are only grabbed when `grab` is set.
 pub fn handle_udev(
     event: UdevEvent,
     keyboard_stream_map: &mut StreamMap<String, EventStream>,
-) -> Result<(), MudrasError> {
+    devices: &mut Devices,
+    grab: bool,
+) -> Result<Option<(String, GrabbedDevice)>, MudrasError> {
     if !event.is_initialized() {
         warn!("Received udev event with uninitialized device.");
     }
@@ -24,14 +27,16 @@ pub fn handle_udev(
         EventType::Add => {
             if let Some(path) = event.devnode() {
                 let node = path.to_str().unwrap();
-                if let Some(mut device) = Device::open(node).ok() {
+                if let Ok(mut device) = Device::open(node) {
                     let name = device.name().unwrap_or("[unknown]").to_string();
-                    if utils::check_device_is_keyboard(&device) {
-                        let _ = device.grab();
+                    if let Some(kind) = devices.register(node, &device) {
+                        if grab && kind != DeviceKind::Switch {
+                            let _ = device.grab();
+                        }
                         keyboard_stream_map.insert(node.to_string(), device.into_event_stream()?);
-                        info!("added keyboard device '{}' at '{}'.", name, node);
-                    } else if utils::check_device_is_pointer(&device) {
-                        info!("added pointer device '{}' at '{}'.", name, node);
+                        info!("added {:?} device '{}' at '{}'.", kind, name, node);
+                    } else {
+                        trace!("ignored device '{}' at '{}'.", name, node);
                     }
                 }
             }
@@ -45,6 +50,7 @@ pub fn handle_udev(
                         .expect("device not in stream_map");
                     let name = stream.device().name().unwrap_or("[unknown]");
                     info!("removed device '{}' at '{}'.", name, node);
+                    return Ok(devices.unregister(node).map(|e| (node.to_owned(), e)));
                 }
             }
         }
@@ -52,5 +58,5 @@ pub fn handle_udev(
             trace!("ignored udev event of type: {:?}", event.event_type());
         }
     }
-    Ok(())
+    Ok(None)
 }
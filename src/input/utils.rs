@@ -1,14 +1,15 @@
-use crate::config::{Bind, BindArgs, BindSequence, Binds, Command, Keyword, Submaps};
-use pipelight_exec::Process;
+use super::{modifier, pointer, switch};
+use crate::config::{BindArgs, Command, Keyword, Submaps};
+use crate::executor::{BindId, Executor};
 
-use evdev::{uinput::VirtualDevice, AbsoluteAxisCode, Device, InputEvent, KeyCode};
+use evdev::{Device, EventType, InputEvent, KeyCode, RelativeAxisCode};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
 // Error
-use crate::error::{LibError, MudrasError, WrapError};
+use crate::error::MudrasError;
 use miette::Result;
-use tracing::{debug, error, info, trace, warn};
 
 pub fn check_device_is_keyboard(device: &Device) -> bool {
     if device
@@ -23,10 +24,25 @@ pub fn check_device_is_keyboard(device: &Device) -> bool {
         false
     }
 }
+pub fn check_device_is_switch(device: &Device) -> bool {
+    if device
+        .supported_switches()
+        .is_some_and(|switches| switches.iter().next().is_some())
+    {
+        if device.name() == Some("Mudras virtual switch") {
+            return false;
+        }
+        true
+    } else {
+        false
+    }
+}
+/// Relative pointers only (mouses, trackballs, trackpoints).
+/// Absolute devices (touchpads, tablets) are left to the compositor.
 pub fn check_device_is_pointer(device: &Device) -> bool {
     if device
-        .supported_absolute_axes()
-        .is_some_and(|axes| axes.contains(AbsoluteAxisCode::ABS_X))
+        .supported_relative_axes()
+        .is_some_and(|axes| axes.contains(RelativeAxisCode::REL_X))
     {
         if device.name() == Some("Mudras virtual pointer") {
             return false;
@@ -37,6 +53,30 @@ pub fn check_device_is_pointer(device: &Device) -> bool {
     }
 }
 
+/// Readable name of a key, pseudo keys included.
+pub fn key_name(key: KeyCode) -> String {
+    match key {
+        pointer::WHEEL_UP => "WheelUp".to_owned(),
+        pointer::WHEEL_DOWN => "WheelDown".to_owned(),
+        pointer::WHEEL_LEFT => "WheelLeft".to_owned(),
+        pointer::WHEEL_RIGHT => "WheelRight".to_owned(),
+        modifier::CTRL => "Ctrl".to_owned(),
+        modifier::SHIFT => "Shift".to_owned(),
+        modifier::ALT => "Alt".to_owned(),
+        modifier::SUPER => "Super".to_owned(),
+        _ => match switch::key_to_switch(key) {
+            Some(switch) => format!("{:?}", switch),
+            None => format!("{:?}", key),
+        },
+    }
+}
+
+/// Whether a key is a pseudo key (wheel, modifier or switch),
+/// they all come after the evdev key range.
+pub fn is_pseudo(key: KeyCode) -> bool {
+    key.code() >= pointer::WHEEL_UP.code()
+}
+
 /// A struct that stores the keyboard current and n-1 state.
 #[derive(Default, Debug, PartialEq)]
 pub struct KeyboardState {
@@ -44,6 +84,148 @@ pub struct KeyboardState {
     pub current: State,
     /// Previous state (keystroke n-1)
     pub previous: State,
+    /// Last press or repeat of every pressed key.
+    pub seen: HashMap<KeyCode, Instant>,
+    /// Pressed keys that weren't forwarded, so that their release isn't either.
+    pub swallowed: HashSet<KeyCode>,
+    /// Presses on their way to a bind, in order, replayed if the bind isn't completed.
+    pub held: Vec<KeyCode>,
+}
+
+impl KeyboardState {
+    /// Update keyboard representation state with a new key event.
+    pub fn update(&mut self, keycode: KeyCode, key_state: &KeyState) {
+        self.previous = self.current.clone();
+        self.current
+            .keys
+            .retain(|_, value| value != &KeyState::Released);
+        self.current.keys.insert(keycode, key_state.to_owned());
+        match key_state {
+            KeyState::Pressed => {
+                self.seen.insert(keycode, Instant::now());
+            }
+            _ => {
+                self.seen.remove(&keycode);
+            }
+        }
+    }
+
+    /// A key repeat proves the key is still physically held.
+    pub fn touch(&mut self, keycode: KeyCode) {
+        if let Some(seen) = self.seen.get_mut(&keycode) {
+            *seen = Instant::now();
+        }
+    }
+
+    /// Drop a key from the state without any release.
+    pub fn forget(&mut self, keycode: KeyCode) {
+        self.current.keys.remove(&keycode);
+        self.seen.remove(&keycode);
+        self.swallowed.remove(&keycode);
+        self.held.retain(|e| *e != keycode);
+    }
+
+    /// Held presses to emit before a forwarded event, as it means they won't complete a bind.
+    /// Held presses are dropped once a bind swallows them.
+    pub fn replay(&mut self, fate: Fate) -> Vec<InputEvent> {
+        match fate {
+            Fate::Forward => std::mem::take(&mut self.held)
+                .into_iter()
+                .map(|key| {
+                    self.swallowed.remove(&key);
+                    InputEvent::new(EventType::KEY.0, key.code(), 1)
+                })
+                .collect(),
+            Fate::Swallow => {
+                self.held.clear();
+                vec![]
+            }
+            Fate::Hold => vec![],
+        }
+    }
+
+    /// Whether a key event is forwarded, given its fate.
+    /// Releases follow the fate of their press.
+    pub fn forward(&mut self, keycode: KeyCode, key_state: &KeyState, fate: Fate) -> bool {
+        match key_state {
+            KeyState::Pressed => {
+                match fate {
+                    Fate::Forward => {
+                        self.swallowed.remove(&keycode);
+                    }
+                    Fate::Swallow => {
+                        self.swallowed.insert(keycode);
+                    }
+                    Fate::Hold => {
+                        self.swallowed.insert(keycode);
+                        self.held.push(keycode);
+                    }
+                }
+                fate == Fate::Forward
+            }
+            _ => !self.swallowed.remove(&keycode),
+        }
+    }
+
+    /// Release keys that haven't been pressed or repeated for longer than the timeout.
+    /// Modifiers are left alone: only the last pressed key is repeated,
+    /// so a modifier held under another key looks just like a stuck one.
+    /// Returns the release events to emit.
+    pub fn release_stuck(&mut self, timeout: Duration, now: Instant) -> Vec<InputEvent> {
+        let stuck: Vec<KeyCode> = self
+            .seen
+            .iter()
+            .filter(|(key, seen)| {
+                !modifier::is_modifier(**key) && now.duration_since(**seen) > timeout
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        let mut events = vec![];
+        for key in stuck {
+            if self.current.keys.get(&key) == Some(&KeyState::Pressed) {
+                events.push(InputEvent::new(EventType::KEY.0, key.code(), 0));
+            }
+            self.forget(key);
+            self.previous.keys.remove(&key);
+        }
+        events
+    }
+
+    /// Merge the states of several devices into a single one,
+    /// so that a key held on a device combines with keys of another.
+    /// The given state has precedence over the others.
+    pub fn merge<'a>(&self, others: impl Iterator<Item = &'a KeyboardState>) -> KeyboardState {
+        let mut merged = KeyboardState::default();
+        for other in others {
+            for (key, value) in &other.current.keys {
+                if value == &KeyState::Pressed {
+                    merged.current.keys.insert(*key, KeyState::Pressed);
+                    merged.previous.keys.insert(*key, KeyState::Pressed);
+                }
+            }
+        }
+        merged.current.keys.extend(self.current.keys.clone());
+        merged.previous.keys.extend(self.previous.keys.clone());
+        merged
+    }
+
+    /// Whether any key is physically held.
+    pub fn is_held(&self) -> bool {
+        self.current.keys.values().any(|e| e == &KeyState::Pressed)
+    }
+
+    /// Release events for every key still pressed that was forwarded.
+    /// Swallowed keys and pseudo keys never reached the virtual device.
+    pub fn release_events(&self) -> Vec<InputEvent> {
+        self.current
+            .keys
+            .iter()
+            .filter(|(key, value)| {
+                value == &&KeyState::Pressed && !self.swallowed.contains(key) && !is_pseudo(**key)
+            })
+            .map(|(key, _)| InputEvent::new(EventType::KEY.0, key.code(), 0))
+            .collect()
+    }
 }
 
 #[derive(Default, Clone, Debug, PartialEq)]
@@ -59,6 +241,17 @@ pub enum KeyState {
     Undefined,
 }
 
+/// What becomes of a key event, once matched against binds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fate {
+    /// Passed to applications.
+    Forward,
+    /// Kept from applications, it triggers a bind.
+    Swallow,
+    /// Kept from applications while on its way to a bind.
+    Hold,
+}
+
 #[derive(Clone, Debug)]
 pub struct SubmapState {
     pub submaps: Submaps,
@@ -74,55 +267,131 @@ impl Default for SubmapState {
 }
 
 /// Compare keyboard state and binds.
+/// Returns the fate of the event:
+/// keys that trigger a bind are swallowed (unless `swallow=false`),
+/// non modifier keys on their way to a bind are held.
 pub fn trigger_action(
     submaps_state: &mut SubmapState,
     keyboard_state: &KeyboardState,
+    keycode: KeyCode,
     key_state: &KeyState,
+    device: Option<&str>,
 
-    virtual_keyboard: &mut VirtualDevice,
-    event: InputEvent,
-) -> Result<(), MudrasError> {
+    executor: &mut Executor,
+) -> Result<Fate, MudrasError> {
     // Get current submap
     let name = submaps_state.current.clone();
     let submap = submaps_state.submaps.get(&name).unwrap();
     // Sort keyboard sequence for comparison
     let mut keyboard_sequence: Vec<(KeyCode, KeyState)> =
         keyboard_state.current.keys.clone().into_iter().collect();
-    keyboard_sequence.sort_by(|a, b| a.0.cmp(&b.0));
+    keyboard_sequence.sort_by_key(|a| a.0);
 
     // trace!("{:#?}", keyboard_sequence);
     // A bind sequence is matched against the current keyboard sequence
-    if let Some(bind_args) = submap.binds.get(&keyboard_sequence) {
+    if let Some((scope, sequence, bind_args)) = submap.find(&keyboard_sequence, device) {
         // Extra step for release keys
         match key_state {
             KeyState::Released => {
                 if keyboard_state.previous.keys.len() > keyboard_state.current.keys.len() {
-                    virtual_keyboard.emit(&[event]).unwrap();
-                    return Ok(());
+                    return Ok(Fate::Forward);
                 }
             }
             KeyState::Pressed => {}
             _ => {}
         }
         // Trigger action as soon as keys are detected.
-        for cmd in &bind_args.commands {
-            match cmd {
-                Command::Sh(stdin) => {
-                    let mut p = Process::new().stdin(&stdin).term().background().to_owned();
-                    let _ = p.run();
+        let id = BindId {
+            submap: name,
+            device: scope.map(|e| e.to_owned()),
+            sequence: sequence.clone(),
+        };
+        let fate = match bind_args.swallow {
+            true => Fate::Swallow,
+            false => Fate::Forward,
+        };
+        if !executor.run(&id, bind_args)? {
+            return Ok(fate);
+        }
+        apply_keywords(&mut submaps_state.current, bind_args);
+        Ok(fate)
+    } else {
+        // Non modifier keys on their way to a bind are kept from applications,
+        // paused submaps only swallow keys that match.
+        let pending = key_state == &KeyState::Pressed
+            && !modifier::is_modifier(keycode)
+            && !submap.pause
+            && submap.is_prefix(&keyboard_sequence, device);
+        match pending {
+            true => Ok(Fate::Hold),
+            false => Ok(Fate::Forward),
+        }
+    }
+}
+
+/// Apply the internal commands (submap enter and exit) of a triggered bind.
+pub fn apply_keywords(current: &mut String, bind_args: &BindArgs) {
+    for cmd in &bind_args.commands {
+        if let Command::Internal(e) = cmd {
+            match e {
+                Keyword::Enter(submap_name) => {
+                    *current = submap_name.to_owned();
+                }
+                Keyword::Exit => {
+                    *current = "main".to_owned();
                 }
-                Command::Internal(e) => match e {
-                    Keyword::Enter(submap_name) => {
-                        submaps_state.current = submap_name.to_owned();
-                    }
-                    Keyword::Exit => {
-                        submaps_state.current = "main".to_owned();
-                    }
-                },
             }
         }
-    } else {
-        virtual_keyboard.emit(&[event]).unwrap();
     }
-    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn release_forwarded_keys_only() {
+        let mut state = KeyboardState::default();
+        for key in [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_F, pointer::WHEEL_UP] {
+            state.update(key, &KeyState::Pressed);
+        }
+        state.forward(KeyCode::KEY_LEFTSHIFT, &KeyState::Pressed, Fate::Forward);
+        state.forward(KeyCode::KEY_F, &KeyState::Pressed, Fate::Swallow);
+        assert!(state.is_held());
+        assert_eq!(
+            vec![InputEvent::new(
+                EventType::KEY.0,
+                KeyCode::KEY_LEFTSHIFT.code(),
+                0
+            )],
+            state.release_events()
+        );
+    }
+
+    #[test]
+    fn release_stuck_keys() {
+        let mut state = KeyboardState::default();
+        state.update(KeyCode::KEY_LEFTSHIFT, &KeyState::Pressed);
+        state.update(KeyCode::KEY_A, &KeyState::Pressed);
+
+        let timeout = Duration::from_secs(30);
+        let now = Instant::now();
+        assert_eq!(Vec::<InputEvent>::new(), state.release_stuck(timeout, now));
+
+        // Neither Shift nor A are repeated, only A is released:
+        // Shift is held without repeats while A is pressed after it.
+        for key in [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_A] {
+            state.seen.insert(key, now - Duration::from_secs(60));
+        }
+        let events = state.release_stuck(timeout, now);
+        assert_eq!(
+            vec![InputEvent::new(EventType::KEY.0, KeyCode::KEY_A.code(), 0)],
+            events
+        );
+        assert_eq!(
+            vec![&KeyCode::KEY_LEFTSHIFT],
+            state.current.keys.keys().collect::<Vec<_>>()
+        );
+    }
 }
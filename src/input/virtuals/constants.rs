@@ -1,7 +1,4 @@
-use evdev::{
-    uinput::{VirtualDevice, VirtualDeviceBuilder},
-    AbsInfo, AbsoluteAxisCode, AttributeSet, KeyCode, RelativeAxisCode, SwitchCode, UinputAbsSetup,
-};
+use evdev::{AbsoluteAxisCode, KeyCode, RelativeAxisCode, SwitchCode};
 
 pub fn get_all_keys() -> &'static [KeyCode] {
     &[
@@ -556,6 +553,19 @@ pub fn get_all_keys() -> &'static [KeyCode] {
     ]
 }
 
+pub fn get_pointer_buttons() -> &'static [KeyCode] {
+    &[
+        KeyCode::BTN_LEFT,
+        KeyCode::BTN_RIGHT,
+        KeyCode::BTN_MIDDLE,
+        KeyCode::BTN_SIDE,
+        KeyCode::BTN_EXTRA,
+        KeyCode::BTN_FORWARD,
+        KeyCode::BTN_BACK,
+        KeyCode::BTN_TASK,
+    ]
+}
+
 pub fn get_all_relative_axes() -> &'static [RelativeAxisCode] {
     &[
         RelativeAxisCode::REL_X,
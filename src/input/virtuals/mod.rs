@@ -1,15 +1,15 @@
 mod constants;
+pub use constants::get_all_switches;
 use constants::*;
 
 use evdev::{
-    uinput::VirtualDevice, AbsInfo, AbsoluteAxisCode, AttributeSet, KeyCode, RelativeAxisCode,
-    SwitchCode, UinputAbsSetup,
+    uinput::VirtualDevice, AbsoluteAxisCode, AttributeSet, KeyCode, RelativeAxisCode, SwitchCode,
 };
 
 // Error
 use crate::error::{LibError, MudrasError};
 use miette::Result;
-use tracing::{debug, error, info, trace, warn};
+use tracing::error;
 
 pub fn create_keyboard() -> Result<VirtualDevice, MudrasError> {
     let keys: AttributeSet<KeyCode> = get_all_keys().iter().copied().collect();
@@ -24,7 +24,7 @@ pub fn create_keyboard() -> Result<VirtualDevice, MudrasError> {
             let help = "";
             error!("{}", message);
             let err = LibError::builder().msg(&message).help(help).build();
-            return Err(err.into());
+            Err(err.into())
         }
     }
 }
@@ -43,20 +43,21 @@ pub fn create_switch() -> Result<VirtualDevice, MudrasError> {
             let help = "";
             error!("{}", message);
             let err = LibError::builder().msg(&message).help(help).build();
-            return Err(err.into());
+            Err(err.into())
         }
     }
 }
 
-/// Deprecated!
+/// Relative pointer forwarding the events of grabbed mouses.
 pub fn create_pointer() -> Result<VirtualDevice, MudrasError> {
+    let buttons: AttributeSet<KeyCode> = get_pointer_buttons().iter().copied().collect();
     let relative_axes: AttributeSet<RelativeAxisCode> =
         get_all_relative_axes().iter().copied().collect();
-    let absolute_axis: Vec<(AbsoluteAxisCode, u16)> =
-        get_all_absolute_axis().iter().copied().collect();
+    let _absolute_axis: Vec<(AbsoluteAxisCode, u16)> = get_all_absolute_axis().to_vec();
 
-    let mut builder = VirtualDevice::builder()?
+    let builder = VirtualDevice::builder()?
         .name("Mudras virtual pointer")
+        .with_keys(&buttons)?
         .with_relative_axes(&relative_axes)?;
 
     // let abs_info = AbsInfo::new(0, i32::MIN, i32::MAX, 0, 0, 0);
@@ -72,7 +73,7 @@ pub fn create_pointer() -> Result<VirtualDevice, MudrasError> {
             let help = "";
             error!("{}", message);
             let err = LibError::builder().msg(&message).help(help).build();
-            return Err(err.into());
+            Err(err.into())
         }
     }
 }
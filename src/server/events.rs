@@ -1,14 +1,22 @@
-use tokio::sync::broadcast::{self, Receiver, Sender};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, Sender};
 
 // Error
-use crate::error::{LibError, MudrasError, WrapError};
+use crate::error::{LibError, MudrasError};
 use miette::Result;
-use tracing::{debug, error, info, trace, warn};
 
 #[derive(Debug, Clone)]
 pub enum Event {
-    Quit,
+    /// Release devices and stop the daemon with the given exit code.
+    Quit(i32),
+    #[allow(dead_code)]
     Action,
+    /// The configuration has been replaced.
+    Reload,
+    /// Release devices and stop matching, until resumed or timed out.
+    Pause(Option<Duration>),
+    /// Grab devices back once every key is released.
+    Resume,
 }
 
 /// Terminal event handler.
@@ -0,0 +1,140 @@
+use super::events::Event;
+use super::Server;
+use crate::config::cheatsheet::Cheatsheet;
+use crate::executor::Stats;
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Socket
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+// Error
+use crate::error::{LibError, MudrasError, WrapError};
+use miette::{Error, Result};
+use tracing::{debug, warn};
+
+/// A request sent to the running daemon.
+/// Requests and responses are exchanged as json lines.
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand, Serialize, Deserialize)]
+pub enum Request {
+    /// Spawned processes and dropped triggers.
+    Stats,
+    /// Release devices and stop matching binds.
+    Pause {
+        /// Resume automatically after that long (ex: "5m").
+        #[arg(long, value_parser = humantime::parse_duration)]
+        timeout: Option<Duration>,
+    },
+    /// Grab devices back and match binds again.
+    Resume,
+    /// Binds of the loaded configuration, by submap.
+    Cheatsheet,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    Ok,
+    Stats(Stats),
+    Cheatsheet(Cheatsheet),
+    Error(String),
+}
+
+/// Return the daemon socket path.
+pub fn socket_path() -> PathBuf {
+    let mut path = match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from("/tmp"),
+    };
+    path.push("mudras.sock");
+    path
+}
+
+impl Server {
+    #[tracing::instrument(skip_all)]
+    pub async fn handle_ipc(&self) -> Result<(), MudrasError> {
+        let path = socket_path();
+        // Remove socket left by a previous instance.
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        debug!("listening on {:?}", path);
+        loop {
+            let (stream, _addr) = listener.accept().await?;
+            let res = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = res.handle_client(stream).await {
+                    warn!("ipc client error: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn handle_client(&self, stream: UnixStream) -> Result<(), MudrasError> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => self.respond(request),
+                Err(e) => Response::Error(e.to_string()),
+            };
+            let mut json = serde_json::to_string(&response)?;
+            json.push('\n');
+            writer.write_all(json.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        match request {
+            Request::Stats => Response::Stats(self.executor.lock().unwrap().stats()),
+            Request::Pause { timeout } => self.forward(Event::Pause(timeout)),
+            Request::Resume => self.forward(Event::Resume),
+            Request::Cheatsheet => {
+                Response::Cheatsheet(Cheatsheet::new(&self.config.read().unwrap()))
+            }
+        }
+    }
+
+    /// Hand the request over to the keyboard loop.
+    fn forward(&self, event: Event) -> Response {
+        match self.events.send(event) {
+            Ok(()) => Response::Ok,
+            Err(e) => Response::Error(e.to_string()),
+        }
+    }
+}
+
+/// Send a request to the running daemon and wait for its response.
+pub async fn send(request: &Request) -> Result<Response, MudrasError> {
+    let path = socket_path();
+    let stream = match UnixStream::connect(&path).await {
+        Ok(v) => v,
+        Err(e) => {
+            let message = format!("Couldn't connect to the daemon socket {:#?}.", path);
+            let help = "Is mudras running? Start it with `mudras run`.";
+            let err = WrapError::builder()
+                .msg(&message)
+                .help(help)
+                .origin(Error::from_err(e))
+                .build();
+            return Err(err.into());
+        }
+    };
+    let (reader, mut writer) = stream.into_split();
+    let mut json = serde_json::to_string(request)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    match lines.next_line().await? {
+        Some(line) => Ok(serde_json::from_str(&line)?),
+        None => {
+            let message = "The daemon closed the connection without responding.";
+            let help = "";
+            Err(LibError::builder().msg(message).help(help).build().into())
+        }
+    }
+}
@@ -1,36 +1,44 @@
 pub mod events;
+pub mod ipc;
 mod signal;
 
 use self::events::{Event, EventHandler};
 use crate::config::Config;
+use crate::executor::Executor;
 
 use bon::bon;
-use futures::StreamExt;
 use tokio::select;
 use tokio::task::JoinHandle;
 
 // Globals
-use std::sync::{Arc, RwLock};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
 // Error
-use crate::error::{LibError, MudrasError, WrapError};
+use crate::error::MudrasError;
 use miette::Result;
-use tracing::{debug, error, info, trace, warn};
 
 #[derive(Debug, Clone)]
 pub struct Server {
-    config: Arc<RwLock<Config>>,
-    events: EventHandler,
+    pub(crate) config: Arc<RwLock<Config>>,
+    /// File the configuration is reloaded from.
+    pub(crate) config_path: PathBuf,
+    pub(crate) events: EventHandler,
+    pub(crate) executor: Arc<Mutex<Executor>>,
     tasks: Vec<Arc<JoinHandle<()>>>,
 }
 
 #[bon]
 impl Server {
     #[builder]
-    pub async fn new(config: Config) -> Result<Self, MudrasError> {
+    pub async fn new(config: Config, config_path: PathBuf) -> Result<Self, MudrasError> {
         let tasks = vec![];
+        let mut executor = Executor::default();
+        executor.set_options(&config.options);
         let res = Self {
             config: Arc::new(RwLock::new(config)),
+            config_path,
             events: EventHandler::default(),
+            executor: Arc::new(Mutex::new(executor)),
             tasks,
         };
         Ok(res)
@@ -51,11 +59,23 @@ impl Server {
                 _ = res.handle_signals().await;
             }
         });
-        self.tasks
-            .extend(vec![Arc::new(rx_task), Arc::new(signal_task)]);
+        let ipc_task: JoinHandle<()> = tokio::spawn({
+            let res = self.clone();
+            async move {
+                _ = res.handle_ipc().await;
+            }
+        });
+        self.tasks.extend(vec![
+            Arc::new(rx_task),
+            Arc::new(signal_task),
+            Arc::new(ipc_task),
+        ]);
         let config = self.config.read().unwrap().clone();
 
-        self.listen_keyboard(&config).await?;
+        let code = self.listen_keyboard(&config).await?;
+        if code != 0 {
+            std::process::exit(code);
+        }
         Ok(())
     }
 }
@@ -73,8 +93,11 @@ impl Server {
                 biased;
                 Ok(event) = receiver.recv() => {
                     match event {
-                        Event::Quit => {}
+                        Event::Quit(_) => {}
                         Event::Action => {}
+                        Event::Reload => {}
+                        Event::Pause(_) => {}
+                        Event::Resume => {}
 
                     }
                 }
@@ -1,5 +1,6 @@
+use super::events::Event;
 use super::Server;
-use crate::input::utils;
+use crate::config::Config;
 
 // Signals
 use signal_hook::consts::signal::*;
@@ -12,9 +13,9 @@ use futures::StreamExt;
 use tokio::select;
 
 // Error
-use crate::error::{LibError, MudrasError, WrapError};
+use crate::error::MudrasError;
 use miette::Result;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{error, info, warn};
 
 impl Server {
     pub async fn handle_signals(&self) -> Result<(), MudrasError> {
@@ -28,33 +29,43 @@ impl Server {
                 Some(signal) = signals.next() => {
                     match signal {
                         SIGUSR1 => {
-                            for mut device in evdev::enumerate().map(|(_, device)| device).filter(utils::check_device_is_keyboard) {
-                                let _ = device.ungrab();
+                            info!("Received SIGUSR1 signal, pausing...");
+                            if let Err(e) = self.events.send(Event::Pause(None)) {
+                                error!("{}", e);
                             }
                         }
                         SIGUSR2 => {
-                            for mut device in evdev::enumerate().map(|(_, device)| device).filter(utils::check_device_is_keyboard) {
-                                let _ = device.grab();
+                            info!("Received SIGUSR2 signal, resuming...");
+                            if let Err(e) = self.events.send(Event::Resume) {
+                                error!("{}", e);
                             }
                         }
                         SIGHUP => {
                             // Update configuration
-
-                        }
-                        SIGINT => {
-                            for mut device in evdev::enumerate().map(|(_, device)| device).filter(utils::check_device_is_keyboard) {
-                                let _ = device.ungrab();
+                            info!("Received SIGHUP signal, reloading configuration...");
+                            match Config::from_file(&self.config_path.display().to_string()) {
+                                Ok(config) => {
+                                    *self.config.write().unwrap() = config;
+                                    if let Err(e) = self.events.send(Event::Reload) {
+                                        error!("{}", e);
+                                    }
+                                }
+                                Err(e) => error!("{:?}", e),
                             }
-                            warn!("Received SIGINT signal, exiting...");
-                            exit(1);
+                        }
+                        SIGINT | SIGTERM | SIGQUIT => {
+                            warn!("Received signal: {:#?}, exiting...", signal);
+                            self.quit(0);
+                        }
+                        // Not fatal: registered so that their default action
+                        // (stopping or killing the daemon with devices grabbed) doesn't apply.
+                        SIGCONT | SIGTSTP | SIGPIPE | SIGVTALRM => {
+                            info!("Received signal: {:#?}, ignored.", signal);
                         }
                         _ => {
-                            for mut device in evdev::enumerate().map(|(_, device)| device).filter(utils::check_device_is_keyboard) {
-                               let _ = device.ungrab();
-                            }
                             warn!("Received signal: {:#?}", signal);
                             warn!("Exiting...");
-                            exit(1);
+                            self.quit(1);
                         }
                     }
                 }
@@ -62,3 +73,13 @@ impl Server {
         }
     }
 }
+
+impl Server {
+    /// Let the keyboard loop release devices before exiting.
+    fn quit(&self, code: i32) {
+        if let Err(e) = self.events.send(Event::Quit(code)) {
+            error!("{}", e);
+            exit(code);
+        }
+    }
+}